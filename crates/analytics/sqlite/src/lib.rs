@@ -0,0 +1,202 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+use turborepo_core::analytics::{AnalyticsSink, AnalyticsSinkError, CacheEvent};
+
+/// Analytics sink appending cache events to a local SQLite database.
+///
+/// Events land in the `cache_events` table, one row per event, so hit rates
+/// and time saved can be computed with plain SQL, e.g.
+///
+/// ```sql
+/// SELECT team_id,
+///        AVG(event = 'HIT') AS hit_rate,
+///        SUM(duration) FILTER (WHERE event = 'HIT') AS time_saved_ms
+/// FROM cache_events
+/// GROUP BY team_id;
+/// ```
+pub struct SqliteAnalyticsSink {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteAnalyticsSink {
+    pub fn builder() -> SqliteAnalyticsSinkBuilder {
+        SqliteAnalyticsSinkBuilder { path: None }
+    }
+}
+
+#[async_trait]
+impl AnalyticsSink for SqliteAnalyticsSink {
+    async fn record(&self, team_id: &str, events: &[CacheEvent]) -> Result<(), AnalyticsSinkError> {
+        let connection = self.connection.clone();
+        let team_id = team_id.to_string();
+        let events = events.to_vec();
+        let recorded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as i64)
+            .unwrap_or_default();
+
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap();
+            let transaction = connection.transaction()?;
+
+            {
+                let mut insert = transaction.prepare_cached(
+                    "INSERT INTO cache_events \
+                     (team_id, session_id, source, event, hash, duration, recorded_at) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )?;
+
+                for event in &events {
+                    insert.execute(params![
+                        team_id,
+                        event.session_id,
+                        event.source.as_str(),
+                        event.event.as_str(),
+                        event.hash,
+                        event.duration,
+                        recorded_at,
+                    ])?;
+                }
+            }
+
+            transaction.commit()
+        })
+        .await
+        .map_err(|err| AnalyticsSinkError(err.into()))?
+        .map_err(|err| AnalyticsSinkError(err.into()))
+    }
+}
+
+pub struct SqliteAnalyticsSinkBuilder {
+    path: Option<PathBuf>,
+}
+
+impl SqliteAnalyticsSinkBuilder {
+    pub async fn build(&self) -> Result<SqliteAnalyticsSink, AnalyticsSinkError> {
+        let path = self.path.clone().unwrap();
+
+        let connection = tokio::task::spawn_blocking(move || {
+            let connection = Connection::open(path)?;
+
+            connection.execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 CREATE TABLE IF NOT EXISTS cache_events (
+                     id INTEGER PRIMARY KEY,
+                     team_id TEXT NOT NULL,
+                     session_id TEXT NOT NULL,
+                     source TEXT NOT NULL,
+                     event TEXT NOT NULL,
+                     hash TEXT NOT NULL,
+                     duration INTEGER,
+                     recorded_at INTEGER NOT NULL
+                 );
+                 CREATE INDEX IF NOT EXISTS cache_events_team_id_recorded_at
+                     ON cache_events (team_id, recorded_at);",
+            )?;
+
+            Ok::<_, rusqlite::Error>(connection)
+        })
+        .await
+        .map_err(|err| AnalyticsSinkError(err.into()))?
+        .map_err(|err| AnalyticsSinkError(err.into()))?;
+
+        Ok(SqliteAnalyticsSink {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub fn with_path(&mut self, path: PathBuf) -> &mut Self {
+        self.path.replace(path);
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turborepo_core::analytics::{CacheEventKind, CacheSource};
+
+    use super::*;
+
+    fn event(source: CacheSource, event: CacheEventKind, hash: &str) -> CacheEvent {
+        CacheEvent {
+            session_id: "3f3ac2c4".into(),
+            source,
+            event,
+            hash: hash.into(),
+            duration: matches!(event, CacheEventKind::Hit).then_some(120),
+        }
+    }
+
+    #[tokio::test]
+    async fn records_events_as_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("analytics.db");
+        let sink = SqliteAnalyticsSink::builder()
+            .with_path(path.clone())
+            .build()
+            .await
+            .unwrap();
+
+        sink.record(
+            "acme",
+            &[
+                event(CacheSource::Remote, CacheEventKind::Hit, "0123abcd"),
+                event(CacheSource::Local, CacheEventKind::Miss, "4567ef"),
+            ],
+        )
+        .await
+        .unwrap();
+        sink.record(
+            "web",
+            &[event(CacheSource::Remote, CacheEventKind::Miss, "89ab")],
+        )
+        .await
+        .unwrap();
+
+        let connection = Connection::open(path).unwrap();
+        let mut select = connection
+            .prepare(
+                "SELECT team_id, session_id, source, event, hash, duration, recorded_at \
+                 FROM cache_events ORDER BY id",
+            )
+            .unwrap();
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<u64>>(5)?,
+                    row.get::<_, i64>(6)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let expected = [
+            ("acme", "REMOTE", "HIT", "0123abcd", Some(120)),
+            ("acme", "LOCAL", "MISS", "4567ef", None),
+            ("web", "REMOTE", "MISS", "89ab", None),
+        ];
+        assert_eq!(rows.len(), expected.len());
+        for (row, (team_id, source, event, hash, duration)) in rows.iter().zip(expected) {
+            assert_eq!(row.0, team_id);
+            assert_eq!(row.1, "3f3ac2c4");
+            assert_eq!(row.2, source);
+            assert_eq!(row.3, event);
+            assert_eq!(row.4, hash);
+            assert_eq!(row.5, duration);
+            assert!(row.6 > 0);
+        }
+    }
+}
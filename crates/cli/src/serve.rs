@@ -1,29 +1,200 @@
-use std::{fmt, sync::Arc};
+use std::{fmt, path::PathBuf, sync::Arc, time::Duration};
 
 use clap::{Parser, ValueEnum};
 use turborepo_aws_s3_storage_adapter::AwsS3StorageAdapter;
-use turborepo_core::TurborepoCore;
+use turborepo_azure_blob_storage_adapter::AzureBlobStorageAdapter;
+use turborepo_core::{TurborepoCore, TurborepoCoreBuilder};
 use turborepo_fs_storage_adapter::FsStorageAdapter;
-use turborepo_server::TurborepoServer;
+use turborepo_gcs_storage_adapter::GcsStorageAdapter;
+use turborepo_memory_storage_adapter::MemoryStorageAdapter;
+use turborepo_postgres_storage_adapter::PostgresStorageAdapter;
+use turborepo_redb_storage_adapter::RedbStorageAdapter;
+use turborepo_redis_storage_adapter::RedisStorageAdapter;
+use turborepo_server::{JwtVerifier, TokenRegistry, TurborepoServer, TurborepoServerBuilder};
+use turborepo_sqlite_analytics_sink::SqliteAnalyticsSink;
+use turborepo_sqlite_storage_adapter::SqliteStorageAdapter;
 
 #[derive(Clone, Debug, ValueEnum)]
 enum Storage {
     Fs,
     Aws,
+    Azure,
+    Gcs,
+    Memory,
+    Embedded,
+    Postgres,
+    Redis,
+    Sqlite,
 }
 
 #[derive(Debug, Parser)]
 pub struct Serve {
-    #[arg(long, default_missing_value = "127.0.0.1")]
+    #[arg(long, default_value = "127.0.0.1")]
     api_address: String,
     #[arg(long)]
     api_port: u16,
+    /// Directory, bucket, Azure container or database file the artifacts are
+    /// stored in.
+    #[arg(long, required_if_eq_any = [("storage", "fs"), ("storage", "aws"), ("storage", "azure"), ("storage", "gcs"), ("storage", "sqlite"), ("storage", "embedded")])]
+    bucket: Option<String>,
+    /// Token granting read-write access to every team.
+    #[arg(
+        long,
+        env = "TURBOREPO_TOKEN",
+        hide_env_values = true,
+        required_unless_present_any = ["token_file", "jwt_secret", "jwks_file"]
+    )]
+    token: Option<String>,
+    /// JSON file listing tokens restricted to some teams and scope.
     #[arg(long)]
-    bucket: String,
+    token_file: Option<PathBuf>,
+    /// Shared secret of HS256-signed JWTs.
+    #[arg(long, env = "TURBOREPO_JWT_SECRET", hide_env_values = true)]
+    jwt_secret: Option<String>,
+    /// JWKS file holding the public keys of RS256/ES256-signed JWTs.
     #[arg(long)]
-    token: String,
+    jwks_file: Option<PathBuf>,
+    /// Audience JWTs must be issued for. Can be repeated.
+    #[arg(long)]
+    jwt_audience: Vec<String>,
+    /// JWT claim holding the team(s) a token can access.
+    #[arg(long, default_value = "team")]
+    jwt_team_claim: String,
+    /// JWT claim holding the scope of a token, `read` or `read-write`. Tokens
+    /// without it can only read.
+    #[arg(long, default_value = "scope")]
+    jwt_scope_claim: String,
     #[arg(long, value_enum, default_value_t = Storage::Fs, default_missing_value = "fs",)]
     storage: Storage,
+    /// Maximum size, in bytes, of the artifacts kept by the memory storage.
+    /// The least recently used ones are evicted beyond it.
+    #[arg(long)]
+    memory_max_size: Option<u64>,
+    /// Connection URL of the PostgreSQL database, as
+    /// `postgres://<USER>:<PASSWORD>@<HOST>[:<PORT>]/<DATABASE>`.
+    #[arg(
+        long,
+        env = "TURBOREPO_POSTGRES_URL",
+        hide_env_values = true,
+        required_if_eq("storage", "postgres")
+    )]
+    postgres_url: Option<String>,
+    /// Size of the PostgreSQL connection pool.
+    #[arg(long)]
+    postgres_max_connections: Option<u32>,
+    /// URL of the Redis server, as `redis://[<USER>][:<PASSWORD>@]<HOST>[:<PORT>][/<DB>]`.
+    #[arg(
+        long,
+        env = "TURBOREPO_REDIS_URL",
+        hide_env_values = true,
+        default_value = "redis://127.0.0.1:6379"
+    )]
+    redis_url: String,
+    /// Prefix of the Redis keys artifacts are stored under.
+    #[arg(long, default_value = "turborepo:")]
+    redis_key_prefix: String,
+    /// Seconds after which artifacts stored in Redis expire. They never do
+    /// if unset.
+    #[arg(long)]
+    redis_ttl: Option<u64>,
+    /// Size, in bytes, above which artifacts are rejected by the Redis
+    /// storage; 16 MiB by default.
+    #[arg(long)]
+    redis_max_artifact_size: Option<usize>,
+    /// URL of an S3-compatible service, such as MinIO, to use instead of AWS.
+    #[arg(long)]
+    aws_endpoint_url: Option<String>,
+    /// Addresses S3 buckets path-style, as `<endpoint>/<bucket>/<key>`, as
+    /// services such as MinIO usually need.
+    #[arg(long)]
+    s3_force_path_style: bool,
+    /// Region of the bucket, overriding the environment and the profile.
+    #[arg(long)]
+    aws_region: Option<String>,
+    /// Access key ID, used with --aws-secret-access-key instead of the
+    /// credentials of the environment or the profile.
+    #[arg(
+        long,
+        env = "TURBOREPO_AWS_ACCESS_KEY_ID",
+        hide_env_values = true,
+        requires = "aws_secret_access_key"
+    )]
+    aws_access_key_id: Option<String>,
+    #[arg(
+        long,
+        env = "TURBOREPO_AWS_SECRET_ACCESS_KEY",
+        hide_env_values = true,
+        requires = "aws_access_key_id"
+    )]
+    aws_secret_access_key: Option<String>,
+    /// Named profile of the shared AWS config files.
+    #[arg(long)]
+    aws_profile: Option<String>,
+    /// Prefix the artifacts are stored under, inside the bucket.
+    #[arg(long)]
+    aws_prefix: Option<String>,
+    /// Service account JSON key file authenticating with GCS. Defaults to
+    /// `GOOGLE_APPLICATION_CREDENTIALS`, or to the metadata server (workload
+    /// identity) if unset.
+    #[arg(long)]
+    gcs_credentials_file: Option<PathBuf>,
+    /// URL of a GCS emulator, such as fake-gcs-server, to use instead of
+    /// Google.
+    #[arg(long)]
+    gcs_endpoint: Option<String>,
+    /// Sends GCS requests without credentials, for emulators.
+    #[arg(long, conflicts_with = "gcs_credentials_file")]
+    gcs_anonymous: bool,
+    /// Connection string of the Azure storage account.
+    #[arg(
+        long,
+        env = "TURBOREPO_AZURE_CONNECTION_STRING",
+        hide_env_values = true,
+        conflicts_with_all = ["azure_account", "azure_sas_token"]
+    )]
+    azure_connection_string: Option<String>,
+    /// Azure storage account, authenticated with --azure-access-key.
+    #[arg(long, requires = "azure_access_key")]
+    azure_account: Option<String>,
+    #[arg(
+        long,
+        env = "TURBOREPO_AZURE_ACCESS_KEY",
+        hide_env_values = true,
+        requires = "azure_account"
+    )]
+    azure_access_key: Option<String>,
+    /// Shared access signature authenticating with Azure; requires
+    /// --azure-endpoint.
+    #[arg(
+        long,
+        env = "TURBOREPO_AZURE_SAS_TOKEN",
+        hide_env_values = true,
+        requires = "azure_endpoint",
+        conflicts_with = "azure_account"
+    )]
+    azure_sas_token: Option<String>,
+    /// URL of the Azure blob service, such as the one of Azurite.
+    #[arg(long)]
+    azure_endpoint: Option<String>,
+    /// Size, in bytes, of the parts of S3 multipart uploads; at least 5 MiB.
+    #[arg(long)]
+    aws_part_size: Option<usize>,
+    /// Number of parts of an S3 upload sent at once.
+    #[arg(long)]
+    aws_upload_concurrency: Option<usize>,
+    /// Requires the uploads of a team to be signed, as `<TEAM>=<KEY>`. Can be
+    /// repeated, or comma-separated.
+    #[arg(
+        long,
+        env = "TURBOREPO_SIGNING_KEYS",
+        hide_env_values = true,
+        value_delimiter = ',',
+        value_parser = parse_signing_key
+    )]
+    signing_key: Vec<(String, String)>,
+    /// SQLite database recording the cache hits and misses reported by turbo.
+    #[arg(long)]
+    analytics_db: Option<PathBuf>,
 }
 
 impl fmt::Display for Storage {
@@ -34,6 +205,13 @@ impl fmt::Display for Storage {
             match self {
                 Storage::Fs => "fs",
                 Storage::Aws => "aws",
+                Storage::Azure => "azure",
+                Storage::Gcs => "gcs",
+                Storage::Memory => "memory",
+                Storage::Embedded => "embedded",
+                Storage::Postgres => "postgres",
+                Storage::Redis => "redis",
+                Storage::Sqlite => "sqlite",
             }
         )
     }
@@ -41,45 +219,243 @@ impl fmt::Display for Storage {
 
 impl Serve {
     pub async fn run(&self) -> Result<(), anyhow::Error> {
+        let mut core = self.core().await?;
+        self.with_storage(&mut core).await?;
+
+        self.server()?
+            .with_core(core.build().await?)
+            .build()
+            .listen()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sets the storage picked by `--storage` on `core`.
+    async fn with_storage(&self, core: &mut TurborepoCoreBuilder) -> Result<(), anyhow::Error> {
         match self.storage {
-            Storage::Aws => {
-                TurborepoServer::builder()
-                    .with_token(self.token.clone())
-                    .with_core(
-                        TurborepoCore::builder()
-                            .with_storage(Arc::new(
-                                AwsS3StorageAdapter::builder()
-                                    .with_bucket(self.bucket.clone())
-                                    .build()
-                                    .await,
-                            ))
-                            .build()
-                            .await?,
-                    )
+            Storage::Aws => core.with_storage(Arc::new(self.aws_storage().await?)),
+            Storage::Fs => core.with_storage(Arc::new(
+                FsStorageAdapter::builder()
+                    .with_bucket(self.bucket()?)
                     .build()
-                    .listen()
-                    .await?;
-            }
-            Storage::Fs => {
-                TurborepoServer::builder()
-                    .with_token(self.token.clone())
-                    .with_core(
-                        TurborepoCore::builder()
-                            .with_storage(Arc::new(
-                                FsStorageAdapter::builder()
-                                    .with_bucket(self.bucket.clone())
-                                    .build()
-                                    .await,
-                            ))
-                            .build()
-                            .await?,
-                    )
+                    .await,
+            )),
+            Storage::Azure => core.with_storage(Arc::new(self.azure_storage()?)),
+            Storage::Gcs => core.with_storage(Arc::new(self.gcs_storage()?)),
+            Storage::Memory => core.with_storage(Arc::new(self.memory_storage())),
+            Storage::Embedded => core.with_storage(Arc::new(
+                RedbStorageAdapter::builder()
+                    .with_path(self.bucket()?.into())
                     .build()
-                    .listen()
-                    .await?
-            }
+                    .await?,
+            )),
+            Storage::Postgres => core.with_storage(Arc::new(self.postgres_storage().await?)),
+            Storage::Redis => core.with_storage(Arc::new(self.redis_storage().await?)),
+            Storage::Sqlite => core.with_storage(Arc::new(
+                SqliteStorageAdapter::builder()
+                    .with_path(self.bucket()?.into())
+                    .build()
+                    .await?,
+            )),
         };
 
         Ok(())
     }
+
+    async fn core(&self) -> Result<TurborepoCoreBuilder, anyhow::Error> {
+        let mut builder = TurborepoCore::builder();
+
+        for (team_id, key) in &self.signing_key {
+            builder.with_signing_key(team_id.clone(), key.clone().into_bytes());
+        }
+
+        if let Some(analytics_db) = &self.analytics_db {
+            builder.with_analytics(Arc::new(
+                SqliteAnalyticsSink::builder()
+                    .with_path(analytics_db.clone())
+                    .build()
+                    .await?,
+            ));
+        }
+
+        Ok(builder)
+    }
+
+    fn bucket(&self) -> Result<String, anyhow::Error> {
+        self.bucket
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("--storage {} requires --bucket", self.storage))
+    }
+
+    fn azure_storage(&self) -> Result<AzureBlobStorageAdapter, anyhow::Error> {
+        let mut builder = AzureBlobStorageAdapter::builder();
+        builder.with_container(self.bucket()?);
+
+        if let Some(connection_string) = &self.azure_connection_string {
+            builder.with_connection_string(connection_string)?;
+        } else if let (Some(account), Some(access_key)) =
+            (&self.azure_account, &self.azure_access_key)
+        {
+            builder.with_shared_key(account.clone(), access_key)?;
+        } else if let Some(sas_token) = &self.azure_sas_token {
+            builder.with_sas_token(sas_token);
+        } else {
+            anyhow::bail!(
+                "--storage azure requires --azure-connection-string, --azure-account or --azure-sas-token"
+            );
+        }
+
+        if let Some(endpoint) = &self.azure_endpoint {
+            builder.with_endpoint(endpoint.clone());
+        }
+
+        Ok(builder.build()?)
+    }
+
+    fn gcs_storage(&self) -> Result<GcsStorageAdapter, anyhow::Error> {
+        let mut builder = GcsStorageAdapter::builder();
+        builder.with_bucket(self.bucket()?);
+
+        if let Some(endpoint) = &self.gcs_endpoint {
+            builder.with_endpoint(endpoint.clone());
+        }
+
+        if let Some(credentials_file) = &self.gcs_credentials_file {
+            builder.with_service_account_file(credentials_file)?;
+        }
+
+        if self.gcs_anonymous {
+            builder.with_anonymous_access();
+        }
+
+        Ok(builder.build()?)
+    }
+
+    fn memory_storage(&self) -> MemoryStorageAdapter {
+        let mut builder = MemoryStorageAdapter::builder();
+
+        if let Some(max_size) = self.memory_max_size {
+            builder.with_max_size(max_size);
+        }
+
+        builder.build()
+    }
+
+    async fn postgres_storage(&self) -> Result<PostgresStorageAdapter, anyhow::Error> {
+        let mut builder = PostgresStorageAdapter::builder();
+
+        if let Some(url) = &self.postgres_url {
+            builder.with_url(url.clone());
+        }
+
+        if let Some(max_connections) = self.postgres_max_connections {
+            builder.with_max_connections(max_connections);
+        }
+
+        Ok(builder.build().await?)
+    }
+
+    async fn redis_storage(&self) -> Result<RedisStorageAdapter, anyhow::Error> {
+        let mut builder = RedisStorageAdapter::builder();
+        builder
+            .with_url(self.redis_url.clone())
+            .with_key_prefix(self.redis_key_prefix.clone());
+
+        if let Some(ttl) = self.redis_ttl {
+            builder.with_ttl(Duration::from_secs(ttl));
+        }
+
+        if let Some(max_artifact_size) = self.redis_max_artifact_size {
+            builder.with_max_artifact_size(max_artifact_size);
+        }
+
+        Ok(builder.build().await?)
+    }
+
+    async fn aws_storage(&self) -> Result<AwsS3StorageAdapter, anyhow::Error> {
+        let mut builder = AwsS3StorageAdapter::builder();
+        builder.with_bucket(self.bucket()?);
+
+        if let Some(endpoint_url) = &self.aws_endpoint_url {
+            builder.with_endpoint_url(endpoint_url)?;
+        }
+
+        if self.s3_force_path_style {
+            builder.with_force_path_style(true);
+        }
+
+        if let Some(region) = &self.aws_region {
+            builder.with_region(region.clone());
+        }
+
+        if let (Some(access_key_id), Some(secret_access_key)) =
+            (&self.aws_access_key_id, &self.aws_secret_access_key)
+        {
+            builder.with_access_key(access_key_id.clone(), secret_access_key.clone());
+        }
+
+        if let Some(profile) = &self.aws_profile {
+            builder.with_profile(profile.clone());
+        }
+
+        if let Some(prefix) = &self.aws_prefix {
+            builder.with_prefix(prefix.clone());
+        }
+
+        if let Some(part_size) = self.aws_part_size {
+            builder.with_part_size(part_size);
+        }
+
+        if let Some(upload_concurrency) = self.aws_upload_concurrency {
+            builder.with_upload_concurrency(upload_concurrency);
+        }
+
+        Ok(builder.build().await)
+    }
+
+    fn server(&self) -> Result<TurborepoServerBuilder, anyhow::Error> {
+        let mut builder = TurborepoServer::builder();
+        builder.with_address(self.api_address.clone(), self.api_port);
+
+        if let Some(token) = &self.token {
+            builder.with_token(token.clone());
+        }
+
+        if let Some(token_file) = &self.token_file {
+            builder.with_token_registry(TokenRegistry::from_file(token_file)?);
+        }
+
+        if self.jwt_secret.is_some() || self.jwks_file.is_some() {
+            let mut verifier = JwtVerifier::builder();
+            verifier
+                .with_team_claim(self.jwt_team_claim.clone())
+                .with_scope_claim(self.jwt_scope_claim.clone());
+
+            if let Some(secret) = &self.jwt_secret {
+                verifier.with_secret(secret.clone().into_bytes());
+            }
+
+            if let Some(jwks_file) = &self.jwks_file {
+                verifier.with_jwks_file(jwks_file)?;
+            }
+
+            for audience in &self.jwt_audience {
+                verifier.with_audience(audience.clone());
+            }
+
+            builder.with_jwt_verifier(verifier.build()?);
+        }
+
+        Ok(builder)
+    }
+}
+
+fn parse_signing_key(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((team_id, key)) if !team_id.is_empty() && !key.is_empty() => {
+            Ok((team_id.to_string(), key.to_string()))
+        }
+        _ => Err("expected <TEAM>=<KEY>".into()),
+    }
 }
@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Where `turbo` found, or failed to find, an artifact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CacheSource {
+    Local,
+    Remote,
+}
+
+impl CacheSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheSource::Local => "LOCAL",
+            CacheSource::Remote => "REMOTE",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CacheEventKind {
+    Hit,
+    Miss,
+}
+
+impl CacheEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheEventKind::Hit => "HIT",
+            CacheEventKind::Miss => "MISS",
+        }
+    }
+}
+
+/// A cache hit or miss, as reported by `turbo` to `/v8/artifacts/events`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEvent {
+    pub session_id: String,
+    pub source: CacheSource,
+    pub event: CacheEventKind,
+    pub hash: String,
+    /// Time, in milliseconds, saved by the hit.
+    pub duration: Option<u64>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("analytics sink error: {0}")]
+pub struct AnalyticsSinkError(#[source] pub Box<dyn std::error::Error + Send + Sync>);
+
+/// Destination of the cache events reported by `turbo`.
+#[async_trait]
+pub trait AnalyticsSink: Send + Sync {
+    async fn record(&self, team_id: &str, events: &[CacheEvent]) -> Result<(), AnalyticsSinkError>;
+}
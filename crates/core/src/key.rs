@@ -0,0 +1,188 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+/// Longest accepted artifact hash.
+const MAX_ARTIFACT_ID_LEN: usize = 128;
+/// Longest accepted team id or slug.
+const MAX_TEAM_ID_LEN: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidKeyError {
+    #[error("invalid artifact id `{0}`: expected a hex hash of at most 128 characters")]
+    ArtifactId(String),
+    #[error(
+        "invalid team id `{0}`: expected at most 100 letters, digits, `-` or `_`, starting with a letter or a digit"
+    )]
+    TeamId(String),
+}
+
+/// Hash of an artifact, as computed by `turbo`: ASCII hex digits only.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ArtifactId(String);
+
+impl ArtifactId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ArtifactId {
+    type Err = InvalidKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = !s.is_empty()
+            && s.len() <= MAX_ARTIFACT_ID_LEN
+            && s.bytes().all(|b| b.is_ascii_hexdigit());
+
+        if !valid {
+            return Err(InvalidKeyError::ArtifactId(s.into()));
+        }
+
+        Ok(ArtifactId(s.into()))
+    }
+}
+
+impl fmt::Display for ArtifactId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Id or slug of a team. It is used as a directory of the storage, so it is
+/// limited to ASCII letters, digits, `-` and `_`, and can't start with a
+/// separator.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TeamId(String);
+
+impl TeamId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for TeamId {
+    type Err = InvalidKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = s.len() <= MAX_TEAM_ID_LEN
+            && s.bytes().next().is_some_and(|b| b.is_ascii_alphanumeric())
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+
+        if !valid {
+            return Err(InvalidKeyError::TeamId(s.into()));
+        }
+
+        Ok(TeamId(s.into()))
+    }
+}
+
+impl fmt::Display for TeamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Location of an artifact: the artifact of a team.
+///
+/// Both parts are validated, so the storage path built from them can't
+/// escape the team directory.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ArtifactKey {
+    pub team_id: TeamId,
+    pub artifact_id: ArtifactId,
+}
+
+impl ArtifactKey {
+    pub fn new(team_id: TeamId, artifact_id: ArtifactId) -> ArtifactKey {
+        ArtifactKey {
+            team_id,
+            artifact_id,
+        }
+    }
+
+    /// Path of the artifact in the storage, `<team_id>/<artifact_id>`.
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(self.team_id.as_str()).join(self.artifact_id.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_hex_artifact_ids() {
+        for id in [
+            "0",
+            "0123456789abcdef",
+            "ABCDEF",
+            &"f".repeat(MAX_ARTIFACT_ID_LEN),
+        ] {
+            assert_eq!(id.parse::<ArtifactId>().unwrap().as_str(), id);
+        }
+    }
+
+    #[test]
+    fn rejects_other_artifact_ids() {
+        let too_long = "f".repeat(MAX_ARTIFACT_ID_LEN + 1);
+
+        for id in [
+            "",
+            "..",
+            "../0123",
+            "01/23",
+            "01\\23",
+            "0123\0",
+            "0123xyz",
+            "0123 ",
+            "０１２３",
+            &too_long,
+        ] {
+            assert!(id.parse::<ArtifactId>().is_err(), "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn accepts_team_ids_and_slugs() {
+        for id in [
+            "acme",
+            "team_1234",
+            "my-team",
+            "0team",
+            &"a".repeat(MAX_TEAM_ID_LEN),
+        ] {
+            assert_eq!(id.parse::<TeamId>().unwrap().as_str(), id);
+        }
+    }
+
+    #[test]
+    fn rejects_other_team_ids() {
+        let too_long = "a".repeat(MAX_TEAM_ID_LEN + 1);
+
+        for id in [
+            "",
+            ".",
+            "..",
+            "../acme",
+            "acme/web",
+            "/acme",
+            "acme\\web",
+            "acme\0",
+            "-acme",
+            "_acme",
+            ".acme",
+            "acme web",
+            "acmé",
+            &too_long,
+        ] {
+            assert!(id.parse::<TeamId>().is_err(), "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn paths_stay_in_the_team_directory() {
+        let key = ArtifactKey::new("acme".parse().unwrap(), "0123abcd".parse().unwrap());
+
+        assert_eq!(key.path(), PathBuf::from("acme").join("0123abcd"));
+    }
+}
@@ -1,20 +1,59 @@
-use std::{path::PathBuf, sync::Arc};
+pub mod analytics;
+mod key;
+pub mod signature;
 
-use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use futures::StreamExt;
 use hyper::Body;
-use turborepo_storage_adapter::{StorageAdapter, StorageAdapterError};
+use tokio::{
+    fs,
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+pub use turborepo_storage_adapter::{ArtifactMetadata, StorageAdapterError};
+use turborepo_storage_adapter::{ObjectInfo, StorageAdapter};
+
+pub use crate::key::{ArtifactId, ArtifactKey, InvalidKeyError, TeamId};
+use crate::{
+    analytics::{AnalyticsSink, AnalyticsSinkError, CacheEvent},
+    signature::TagVerifier,
+};
 
 #[derive(Debug)]
 pub enum TurborepoError {
     Unknown,
+    NotFound,
+    InvalidKey(InvalidKeyError),
     StorageAdapter(StorageAdapterError),
+    /// The `x-artifact-tag` of an upload is missing or doesn't match the
+    /// artifact, while the team has a signing key.
+    InvalidSignature,
+    Body(hyper::Error),
+    Analytics(AnalyticsSinkError),
 }
 
 impl std::error::Error for TurborepoError {}
 
 impl std::fmt::Display for TurborepoError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            TurborepoError::Unknown => write!(f, "unknown error"),
+            TurborepoError::NotFound => write!(f, "artifact not found"),
+            TurborepoError::InvalidKey(err) => write!(f, "{}", err),
+            TurborepoError::StorageAdapter(err) => write!(f, "storage error: {}", err),
+            TurborepoError::InvalidSignature => write!(f, "invalid artifact signature"),
+            TurborepoError::Body(err) => write!(f, "can't read artifact: {}", err),
+            TurborepoError::Analytics(err) => write!(f, "{}", err),
+        }
     }
 }
 
@@ -24,11 +63,83 @@ impl From<StorageAdapterError> for TurborepoError {
     }
 }
 
+impl From<InvalidKeyError> for TurborepoError {
+    fn from(value: InvalidKeyError) -> Self {
+        TurborepoError::InvalidKey(value)
+    }
+}
+
+impl From<AnalyticsSinkError> for TurborepoError {
+    fn from(value: AnalyticsSinkError) -> Self {
+        TurborepoError::Analytics(value)
+    }
+}
+
+impl From<hyper::Error> for TurborepoError {
+    fn from(value: hyper::Error) -> Self {
+        TurborepoError::Body(value)
+    }
+}
+
+/// An artifact streamed back from the storage, along with its metadata.
+///
+/// `metadata` is `None` for artifacts stored before metadata was recorded.
+pub struct CachedArtifact {
+    pub body: Body,
+    /// Size of the artifact, in bytes.
+    pub size: u64,
+    pub metadata: Option<ArtifactMetadata>,
+}
+
+/// What is known of a stored artifact, without reading it.
+#[derive(Clone, Debug)]
+pub struct CachedArtifactInfo {
+    /// Size of the artifact, in bytes.
+    pub size: u64,
+    pub last_modified: Option<SystemTime>,
+    pub metadata: Option<ArtifactMetadata>,
+}
+
+/// Details sent by the client along an artifact upload.
+#[derive(Clone, Debug, Default)]
+pub struct ArtifactUpload {
+    /// The `teamId` the client signed the artifact with, which differs from
+    /// the team it is stored under when the team is addressed by its slug.
+    /// `turbo` signs with an empty team id when it only knows the slug.
+    pub signing_team_id: Option<String>,
+    /// The `x-artifact-duration` header.
+    pub duration: Option<u64>,
+    /// The `x-artifact-tag` header.
+    pub tag: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// Outcome of looking up an artifact with
+/// [`TurborepoCore::query_cached_artifacts`].
+#[derive(Clone, Debug)]
+pub enum ArtifactStatus {
+    Missing,
+    /// The artifact exists. Its metadata is `None` when the artifact was
+    /// stored before metadata was recorded.
+    Found {
+        /// Size of the artifact, in bytes.
+        size: u64,
+        metadata: Option<ArtifactMetadata>,
+    },
+}
+
+/// Default number of artifacts [`TurborepoCore::query_cached_artifacts`]
+/// looks up at once.
+const QUERY_CONCURRENCY: usize = 16;
+
 /// Core functionality to check exsistence, download and upload artifacts.
 ///
 /// At its core, it is a thin wrapper around
 pub struct TurborepoCore {
     storage: Arc<dyn StorageAdapter + Sync + Send>,
+    signing_keys: HashMap<String, Vec<u8>>,
+    query_concurrency: usize,
+    analytics: Option<Arc<dyn AnalyticsSink>>,
 }
 
 pub struct TurborepoCoreBuilder
@@ -36,6 +147,9 @@ pub struct TurborepoCoreBuilder
 //     S: StorageAdapter + 'static,
 {
     storage: Option<Arc<dyn StorageAdapter + Sync + Send>>,
+    signing_keys: HashMap<String, Vec<u8>>,
+    query_concurrency: Option<usize>,
+    analytics: Option<Arc<dyn AnalyticsSink>>,
 }
 
 impl TurborepoCore {
@@ -43,46 +157,244 @@ impl TurborepoCore {
 // where
     //     S: StorageAdapter + 'static,
     {
-        TurborepoCoreBuilder { storage: None }
+        TurborepoCoreBuilder {
+            storage: None,
+            signing_keys: HashMap::new(),
+            query_concurrency: None,
+            analytics: None,
+        }
     }
 
     pub async fn get_cached_artifact(
         &self,
-        artifact_id: String,
-        team_id: String,
-    ) -> Result<Bytes, TurborepoError> {
-        Ok(self
-            .storage
-            .get(Self::artifact_path(&artifact_id, &team_id))
-            .await?)
+        key: &ArtifactKey,
+    ) -> Result<CachedArtifact, TurborepoError> {
+        let path = key.path();
+
+        let artifact = self.storage.get_(path.clone()).await.map_err(|err| {
+            if err.is_not_found() {
+                TurborepoError::NotFound
+            } else {
+                err.into()
+            }
+        })?;
+        let metadata = self.storage.get_metadata(path).await?;
+
+        Ok(CachedArtifact {
+            body: artifact.body,
+            size: artifact.size,
+            metadata,
+        })
     }
 
+    /// Stores `artifact`, then its metadata.
+    ///
+    /// The metadata of the artifact it replaces is removed first, so the new
+    /// artifact is never served with the tag of the previous one. When its
+    /// metadata can't be stored, the artifact is removed as well.
+    ///
+    /// When a signing key is configured for the team, the artifact is
+    /// spooled to a temporary file while its signature is computed, and only
+    /// stored if `upload.tag` is valid.
     pub async fn create_cached_artifact(
         &self,
-        artifact_id: String,
-        team_id: String,
+        key: &ArtifactKey,
         artifact: Body,
+        upload: ArtifactUpload,
     ) -> Result<(), TurborepoError> {
-        Ok(self
-            .storage
-            .upload_(Self::artifact_path(&artifact_id, &team_id), artifact)
-            .await?)
+        let path = key.path();
+
+        let artifact = match self.signing_keys.get(key.team_id.as_str()) {
+            Some(signing_key) => {
+                let verifier = TagVerifier::new(
+                    signing_key,
+                    key.artifact_id.as_str(),
+                    upload.signing_team_id.as_deref().unwrap_or_default(),
+                );
+                let (spool, verifier) = spool(artifact, verifier).await?;
+
+                let valid = upload
+                    .tag
+                    .as_deref()
+                    .is_some_and(|tag| verifier.verify(tag));
+                if !valid {
+                    return Err(TurborepoError::InvalidSignature);
+                }
+
+                Body::wrap_stream(ReaderStream::new(spool))
+            }
+            None => artifact,
+        };
+
+        let size = Arc::new(AtomicU64::new(0));
+        let counter = size.clone();
+        let artifact = Body::wrap_stream(artifact.inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+        }));
+
+        self.delete_metadata(&path).await?;
+        self.storage.upload_(path.clone(), artifact).await?;
+
+        let size = size.load(Ordering::Relaxed);
+
+        let metadata = ArtifactMetadata {
+            size,
+            duration: upload.duration,
+            tag: upload.tag,
+            content_type: upload.content_type,
+            uploaded_at: SystemTime::now(),
+        };
+
+        if let Err(err) = self.storage.put_metadata(path.clone(), &metadata).await {
+            // Without its metadata, the artifact would be served unsigned.
+            let _ = self.storage.delete(path).await;
+
+            return Err(err.into());
+        }
+
+        Ok(())
     }
 
-    pub async fn exists_cached_artifact(
+    /// Removes the metadata of the artifact at `path` before it is replaced.
+    /// Storages that can't delete keep it.
+    async fn delete_metadata(&self, path: &Path) -> Result<(), TurborepoError> {
+        match self.storage.delete_metadata(path.to_path_buf()).await {
+            Ok(()) | Err(StorageAdapterError::Unsupported(_)) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns the size, modification time and metadata of an artifact, or
+    /// `None` when it doesn't exist.
+    ///
+    /// The metadata holds the size and upload time of the artifact, so the
+    /// storage is only asked for them when there is none.
+    pub async fn head_cached_artifact(
         &self,
-        artifact_id: &String,
-        team_id: &String,
-    ) -> Result<bool, TurborepoError> {
-        Ok(self
-            .storage
-            .exists(Self::artifact_path(artifact_id, team_id))
-            .await?)
+        key: &ArtifactKey,
+    ) -> Result<Option<CachedArtifactInfo>, TurborepoError> {
+        let path = key.path();
+
+        if let Some(metadata) = self.storage.get_metadata(path.clone()).await? {
+            return Ok(Some(CachedArtifactInfo {
+                size: metadata.size,
+                last_modified: Some(metadata.uploaded_at),
+                metadata: Some(metadata),
+            }));
+        }
+
+        Ok(self.stat(&path).await?.map(|info| CachedArtifactInfo {
+            size: info.size,
+            last_modified: info.last_modified,
+            metadata: None,
+        }))
     }
 
-    fn artifact_path(artifact_id: &String, team_id: &String) -> PathBuf {
-        PathBuf::from(format!("{team_id}/{artifact_id}"))
+    /// Returns the size and modification time of the artifact at `path`.
+    ///
+    /// Storages that can't [`stat`](StorageAdapter::stat) are asked to open
+    /// the artifact instead, which costs a download with those that can't
+    /// stream it either.
+    async fn stat(&self, path: &Path) -> Result<Option<ObjectInfo>, TurborepoError> {
+        match self.storage.stat(path.to_path_buf()).await {
+            Err(StorageAdapterError::Unsupported(_)) => {}
+            result => return Ok(result?),
+        }
+
+        match self.storage.get_(path.to_path_buf()).await {
+            Ok(artifact) => Ok(Some(ObjectInfo {
+                size: artifact.size,
+                last_modified: None,
+            })),
+            Err(err) if err.is_not_found() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn exists_cached_artifact(&self, key: &ArtifactKey) -> Result<bool, TurborepoError> {
+        Ok(self.storage.exists(key.path()).await?)
+    }
+
+    /// Looks up many artifacts of a team at once, running at most
+    /// `query_concurrency` storage lookups concurrently.
+    ///
+    /// Statuses are returned in the order of `artifact_ids`; a failed lookup,
+    /// or an invalid artifact id, doesn't fail the others.
+    pub async fn query_cached_artifacts(
+        &self,
+        artifact_ids: Vec<String>,
+        team_id: &TeamId,
+    ) -> Vec<(String, Result<ArtifactStatus, TurborepoError>)> {
+        futures::stream::iter(artifact_ids)
+            .map(|artifact_id| async move {
+                let status = self.query_cached_artifact(&artifact_id, team_id).await;
+
+                (artifact_id, status)
+            })
+            .buffered(self.query_concurrency)
+            .collect()
+            .await
+    }
+
+    async fn query_cached_artifact(
+        &self,
+        artifact_id: &str,
+        team_id: &TeamId,
+    ) -> Result<ArtifactStatus, TurborepoError> {
+        let path = ArtifactKey::new(team_id.clone(), artifact_id.parse()?).path();
+
+        if let Some(metadata) = self.storage.get_metadata(path.clone()).await? {
+            return Ok(ArtifactStatus::Found {
+                size: metadata.size,
+                metadata: Some(metadata),
+            });
+        }
+
+        Ok(match self.stat(&path).await? {
+            Some(info) => ArtifactStatus::Found {
+                size: info.size,
+                metadata: None,
+            },
+            None => ArtifactStatus::Missing,
+        })
+    }
+
+    /// Hands the cache events reported by `turbo` to the analytics sink. They
+    /// are dropped when no sink is configured.
+    pub async fn record_cache_events(
+        &self,
+        team_id: &TeamId,
+        events: Vec<CacheEvent>,
+    ) -> Result<(), TurborepoError> {
+        if let Some(analytics) = &self.analytics {
+            analytics.record(team_id.as_str(), &events).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes `artifact` to an anonymous temporary file, feeding it to
+/// `verifier` along the way. Returns the file, rewound.
+async fn spool(
+    mut artifact: Body,
+    mut verifier: TagVerifier,
+) -> Result<(fs::File, TagVerifier), TurborepoError> {
+    let mut spool = fs::File::from_std(tempfile::tempfile().map_err(StorageAdapterError::Io)?);
+    while let Some(chunk) = artifact.next().await {
+        let chunk = chunk?;
+        verifier.update(&chunk);
+        spool
+            .write_all(&chunk)
+            .await
+            .map_err(StorageAdapterError::Io)?;
     }
+
+    spool.rewind().await.map_err(StorageAdapterError::Io)?;
+
+    Ok((spool, verifier))
 }
 
 impl TurborepoCoreBuilder
@@ -92,7 +404,16 @@ impl TurborepoCoreBuilder
     pub async fn build(&mut self) -> Result<TurborepoCore, TurborepoError> {
         let storage = self.storage.take().unwrap();
 
-        Ok(TurborepoCore { storage })
+        Ok(TurborepoCore {
+            storage,
+            signing_keys: std::mem::take(&mut self.signing_keys),
+            query_concurrency: self
+                .query_concurrency
+                .take()
+                .unwrap_or(QUERY_CONCURRENCY)
+                .max(1),
+            analytics: self.analytics.take(),
+        })
     }
 
     pub fn with_storage<S: StorageAdapter + Send + Sync + Sized + 'static>(
@@ -103,4 +424,30 @@ impl TurborepoCoreBuilder
 
         self
     }
+
+    /// Requires the uploads of `team_id` to be signed with `key`, the team's
+    /// `TURBO_REMOTE_CACHE_SIGNATURE_KEY`.
+    pub fn with_signing_key(&mut self, team_id: String, key: Vec<u8>) -> &mut Self {
+        self.signing_keys.insert(team_id, key);
+
+        self
+    }
+
+    /// Sets how many artifacts
+    /// [`query_cached_artifacts`](TurborepoCore::query_cached_artifacts) looks
+    /// up at once. Defaults to 16.
+    pub fn with_query_concurrency(&mut self, query_concurrency: usize) -> &mut Self {
+        self.query_concurrency.replace(query_concurrency);
+
+        self
+    }
+
+    pub fn with_analytics<A: AnalyticsSink + Sized + 'static>(
+        &mut self,
+        analytics: Arc<A>,
+    ) -> &mut Self {
+        self.analytics.replace(analytics);
+
+        self
+    }
 }
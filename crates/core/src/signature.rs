@@ -0,0 +1,57 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Checks `tag` against the artifact signature Turborepo computes when
+/// `signature` is enabled in `turbo.json`.
+///
+/// The tag is the base64-encoded HMAC-SHA256, keyed with the team's signing
+/// key, of the artifact hash, the team id and the artifact body, in this order.
+pub fn verify_tag(key: &[u8], artifact_id: &str, team_id: &str, body: &[u8], tag: &str) -> bool {
+    let mut verifier = TagVerifier::new(key, artifact_id, team_id);
+    verifier.update(body);
+
+    verifier.verify(tag)
+}
+
+/// Checks the tag of an artifact read in chunks, as [`verify_tag`] does for
+/// a whole one.
+pub struct TagVerifier(HmacSha256);
+
+impl TagVerifier {
+    pub fn new(key: &[u8], artifact_id: &str, team_id: &str) -> TagVerifier {
+        TagVerifier(tag_generator(key, artifact_id, team_id))
+    }
+
+    /// Feeds the next chunk of the artifact body.
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0.update(chunk);
+    }
+
+    pub fn verify(self, tag: &str) -> bool {
+        let Ok(expected) = STANDARD.decode(tag.trim()) else {
+            return false;
+        };
+
+        self.0.verify_slice(&expected).is_ok()
+    }
+}
+
+/// Computes the tag of an artifact, as [`verify_tag`] expects it.
+pub fn tag(key: &[u8], artifact_id: &str, team_id: &str, body: &[u8]) -> String {
+    let tag = tag_generator(key, artifact_id, team_id)
+        .chain_update(body)
+        .finalize()
+        .into_bytes();
+
+    STANDARD.encode(tag)
+}
+
+fn tag_generator(key: &[u8], artifact_id: &str, team_id: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(key)
+        .expect("HMAC accepts keys of any size")
+        .chain_update(artifact_id.as_bytes())
+        .chain_update(team_id.as_bytes())
+}
@@ -0,0 +1,379 @@
+use std::path::Path;
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::{Grant, Scope, Teams};
+
+#[derive(Debug, thiserror::Error)]
+pub enum JwtVerifierError {
+    #[error("can't read JWKS file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("can't parse JWKS file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("JWKS key #{index} can't be used: {source}")]
+    InvalidKey {
+        index: usize,
+        source: jsonwebtoken::errors::Error,
+    },
+    #[error("a shared secret or a JWKS file is required")]
+    MissingKeys,
+}
+
+/// Validates signed JWTs presented as bearer tokens.
+///
+/// HS256 tokens are checked against a shared secret, RS256 and ES256 tokens
+/// against the keys of a local JWKS file. Besides the signature, `exp`, `nbf`
+/// and, when configured, `aud` are validated. The team a token can access is
+/// read from a configurable claim, holding either a single team or a list of
+/// teams, and its scope from another one, holding `read` or `read-write`. A
+/// token without a scope can only read.
+pub struct JwtVerifier {
+    secret: Option<DecodingKey>,
+    keys: Vec<(Jwk, DecodingKey)>,
+    audience: Vec<String>,
+    team_claim: String,
+    scope_claim: String,
+}
+
+impl JwtVerifier {
+    pub fn builder() -> JwtVerifierBuilder {
+        JwtVerifierBuilder {
+            secret: None,
+            jwks: None,
+            audience: Vec::new(),
+            team_claim: None,
+            scope_claim: None,
+        }
+    }
+
+    pub(crate) fn authenticate(&self, token: &str) -> Option<Grant> {
+        let header = decode_header(token).ok()?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.validate_nbf = true;
+        if !self.audience.is_empty() {
+            validation.set_audience(&self.audience);
+        }
+
+        let claims = self
+            .candidates(&header.alg, header.kid.as_deref())
+            .find_map(|key| decode::<Map<String, Value>>(token, key, &validation).ok())?
+            .claims;
+
+        let teams = match claims.get(&self.team_claim)? {
+            Value::String(team) => vec![team.clone()],
+            Value::Array(teams) => teams
+                .iter()
+                .filter_map(|team| team.as_str().map(String::from))
+                .collect(),
+            _ => return None,
+        };
+
+        if teams.is_empty() {
+            return None;
+        }
+
+        let scope = match claims.get(&self.scope_claim) {
+            None => Scope::Read,
+            Some(scope) => Scope::deserialize(scope).ok()?,
+        };
+
+        Some(Grant {
+            teams: Teams::Only(teams),
+            scope,
+        })
+    }
+
+    /// Keys which may have signed a token using `alg`, narrowed down to the
+    /// key identified by `kid` when the token names one.
+    fn candidates<'a>(
+        &'a self,
+        alg: &'a Algorithm,
+        kid: Option<&'a str>,
+    ) -> Box<dyn Iterator<Item = &'a DecodingKey> + 'a> {
+        match alg {
+            Algorithm::HS256 => Box::new(self.secret.iter()),
+            Algorithm::RS256 | Algorithm::ES256 => Box::new(
+                self.keys
+                    .iter()
+                    .filter(move |(jwk, _)| {
+                        kid.is_none_or(|kid| jwk.common.key_id.as_deref() == Some(kid))
+                    })
+                    .filter(move |(jwk, _)| {
+                        jwk.common.algorithm.is_none_or(|key_alg| key_alg == *alg)
+                    })
+                    .filter(move |(jwk, _)| match (&jwk.algorithm, alg) {
+                        (AlgorithmParameters::RSA(_), Algorithm::RS256) => true,
+                        (AlgorithmParameters::EllipticCurve(params), Algorithm::ES256) => {
+                            params.curve == EllipticCurve::P256
+                        }
+                        _ => false,
+                    })
+                    .map(|(_, key)| key),
+            ),
+            _ => Box::new(std::iter::empty()),
+        }
+    }
+}
+
+pub struct JwtVerifierBuilder {
+    secret: Option<Vec<u8>>,
+    jwks: Option<JwkSet>,
+    audience: Vec<String>,
+    team_claim: Option<String>,
+    scope_claim: Option<String>,
+}
+
+impl JwtVerifierBuilder {
+    pub fn build(&mut self) -> Result<JwtVerifier, JwtVerifierError> {
+        let secret = self
+            .secret
+            .take()
+            .map(|secret| DecodingKey::from_secret(&secret));
+
+        let keys = self
+            .jwks
+            .take()
+            .map(|jwks| jwks.keys)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(index, jwk)| {
+                DecodingKey::from_jwk(&jwk)
+                    .map(|key| (jwk, key))
+                    .map_err(|source| JwtVerifierError::InvalidKey { index, source })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if secret.is_none() && keys.is_empty() {
+            return Err(JwtVerifierError::MissingKeys);
+        }
+
+        Ok(JwtVerifier {
+            secret,
+            keys,
+            audience: std::mem::take(&mut self.audience),
+            team_claim: self.team_claim.take().unwrap_or_else(|| "team".into()),
+            scope_claim: self.scope_claim.take().unwrap_or_else(|| "scope".into()),
+        })
+    }
+
+    /// Accepts HS256 tokens signed with `secret`.
+    pub fn with_secret(&mut self, secret: Vec<u8>) -> &mut Self {
+        self.secret.replace(secret);
+
+        self
+    }
+
+    /// Accepts RS256 and ES256 tokens signed by one of the keys of the JWKS
+    /// file at `path`.
+    pub fn with_jwks_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<&mut Self, JwtVerifierError> {
+        let jwks = serde_json::from_slice(&std::fs::read(path)?)?;
+        self.jwks.replace(jwks);
+
+        Ok(self)
+    }
+
+    /// Requires the `aud` claim to contain `audience`. Can be called several
+    /// times to accept any of a few audiences.
+    pub fn with_audience(&mut self, audience: String) -> &mut Self {
+        self.audience.push(audience);
+
+        self
+    }
+
+    /// Sets the claim holding the team(s) a token can access. Defaults to
+    /// `team`.
+    pub fn with_team_claim(&mut self, claim: String) -> &mut Self {
+        self.team_claim.replace(claim);
+
+        self
+    }
+
+    /// Sets the claim holding the scope of a token, `read` or `read-write`.
+    /// Defaults to `scope`.
+    pub fn with_scope_claim(&mut self, claim: String) -> &mut Self {
+        self.scope_claim.replace(claim);
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    const JWKS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/auth/testdata/jwks.json");
+    const PRIVATE_KEY: &[u8] = include_bytes!("testdata/rsa.pem");
+    const PUBLIC_KEY: &[u8] = include_bytes!("testdata/rsa.pub.pem");
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// Claims of a token valid for another hour.
+    fn claims() -> Map<String, Value> {
+        json!({ "team": "acme", "scope": "read-write", "exp": now() + 3600 })
+            .as_object()
+            .unwrap()
+            .clone()
+    }
+
+    fn hs256(secret: &[u8], claims: &Map<String, Value>) -> String {
+        encode(
+            &Header::new(Algorithm::HS256),
+            claims,
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    fn rs256(claims: &Map<String, Value>) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test".into());
+
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_rsa_pem(PRIVATE_KEY).unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn secret_verifier() -> JwtVerifier {
+        JwtVerifier::builder()
+            .with_secret(b"secret".to_vec())
+            .build()
+            .unwrap()
+    }
+
+    fn jwks_verifier() -> JwtVerifier {
+        JwtVerifier::builder()
+            .with_jwks_file(JWKS)
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn accepts_tokens_signed_with_the_secret_or_the_jwks() {
+        let grant = secret_verifier()
+            .authenticate(&hs256(b"secret", &claims()))
+            .unwrap();
+        assert_eq!(grant.scope, Scope::ReadWrite);
+        assert!(grant.teams.contains("acme"));
+        assert!(!grant.teams.contains("web"));
+
+        let grant = jwks_verifier().authenticate(&rs256(&claims())).unwrap();
+        assert_eq!(grant.scope, Scope::ReadWrite);
+        assert!(grant.teams.contains("acme"));
+    }
+
+    #[test]
+    fn reads_the_scope_claim() {
+        let verifier = secret_verifier();
+
+        let mut claims = claims();
+        claims.insert("scope".into(), "read".into());
+        let grant = verifier.authenticate(&hs256(b"secret", &claims)).unwrap();
+        assert_eq!(grant.scope, Scope::Read);
+
+        claims.remove("scope");
+        let grant = verifier.authenticate(&hs256(b"secret", &claims)).unwrap();
+        assert_eq!(grant.scope, Scope::Read);
+
+        claims.insert("scope".into(), "admin".into());
+        assert!(verifier.authenticate(&hs256(b"secret", &claims)).is_none());
+    }
+
+    #[test]
+    fn rejects_expired_tokens() {
+        let mut claims = claims();
+        // Beyond the default leeway of a minute.
+        claims.insert("exp".into(), (now() - 120).into());
+
+        assert!(secret_verifier()
+            .authenticate(&hs256(b"secret", &claims))
+            .is_none());
+        assert!(jwks_verifier().authenticate(&rs256(&claims)).is_none());
+    }
+
+    #[test]
+    fn rejects_tokens_not_valid_yet() {
+        let mut claims = claims();
+        claims.insert("nbf".into(), (now() + 600).into());
+
+        assert!(secret_verifier()
+            .authenticate(&hs256(b"secret", &claims))
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_tokens_of_other_audiences() {
+        let verifier = JwtVerifier::builder()
+            .with_secret(b"secret".to_vec())
+            .with_audience("turborepo".into())
+            .build()
+            .unwrap();
+
+        let mut claims = claims();
+        claims.insert("aud".into(), "turborepo".into());
+        assert!(verifier.authenticate(&hs256(b"secret", &claims)).is_some());
+
+        claims.insert("aud".into(), "other".into());
+        assert!(verifier.authenticate(&hs256(b"secret", &claims)).is_none());
+    }
+
+    #[test]
+    fn rejects_tokens_without_teams() {
+        let mut claims = claims();
+        claims.insert("team".into(), json!([]));
+
+        assert!(secret_verifier()
+            .authenticate(&hs256(b"secret", &claims))
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_hs256_tokens_without_a_secret() {
+        assert!(jwks_verifier()
+            .authenticate(&hs256(b"secret", &claims()))
+            .is_none());
+    }
+
+    /// An HS256 token using the public key of the JWKS as its secret must not
+    /// pass for one signed with the private key.
+    #[test]
+    fn rejects_hs256_tokens_signed_with_a_public_key() {
+        let verifier = JwtVerifier::builder()
+            .with_secret(b"secret".to_vec())
+            .with_jwks_file(JWKS)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test".into());
+        let token = encode(&header, &claims(), &EncodingKey::from_secret(PUBLIC_KEY)).unwrap();
+
+        assert!(verifier.authenticate(&token).is_none());
+        assert!(verifier.authenticate(&rs256(&claims())).is_some());
+    }
+}
@@ -0,0 +1,262 @@
+mod jwt;
+mod registry;
+
+use hyper::{header::AUTHORIZATION, Body, Method, Request};
+use routerify::prelude::*;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+pub use self::{
+    jwt::{JwtVerifier, JwtVerifierBuilder, JwtVerifierError},
+    registry::{TokenRegistry, TokenRegistryError},
+};
+use crate::{context::RequestContext, error::ServerError, State};
+
+/// What a request may do once its bearer token has been recognised.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Scope {
+    Read,
+    ReadWrite,
+}
+
+impl Scope {
+    fn allows(self, required: Scope) -> bool {
+        matches!(
+            (self, required),
+            (Scope::ReadWrite, _) | (Scope::Read, Scope::Read)
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Teams {
+    Any,
+    Only(Vec<String>),
+}
+
+impl Teams {
+    fn contains(&self, team_id: &str) -> bool {
+        match self {
+            Teams::Any => true,
+            Teams::Only(teams) => teams.iter().any(|team| team == team_id),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Grant {
+    pub teams: Teams,
+    pub scope: Scope,
+}
+
+/// Resolves bearer tokens to grants.
+///
+/// The shared `--token` grants read-write access to every team, while the
+/// entries of a [`TokenRegistry`] and the JWTs accepted by a [`JwtVerifier`]
+/// are limited to their own teams.
+pub struct Authenticator {
+    /// SHA-256 digest of the shared token.
+    token: Option<[u8; 32]>,
+    registry: Option<TokenRegistry>,
+    jwt: Option<JwtVerifier>,
+}
+
+impl Authenticator {
+    pub fn new(
+        token: Option<String>,
+        registry: Option<TokenRegistry>,
+        jwt: Option<JwtVerifier>,
+    ) -> Authenticator {
+        Authenticator {
+            token: token.as_deref().map(digest),
+            registry,
+            jwt,
+        }
+    }
+
+    /// Digests are compared rather than the tokens themselves, so that the
+    /// comparison takes the same time whatever the length of the token.
+    fn authenticate(&self, token: &str) -> Option<Grant> {
+        if let Some(expected) = &self.token {
+            if bool::from(digest(token).ct_eq(expected)) {
+                return Some(Grant {
+                    teams: Teams::Any,
+                    scope: Scope::ReadWrite,
+                });
+            }
+        }
+
+        self.registry
+            .as_ref()
+            .and_then(|registry| registry.authenticate(token))
+            .or_else(|| self.jwt.as_ref().and_then(|jwt| jwt.authenticate(token)))
+    }
+}
+
+/// Pre-middleware guarding the artifact routes with the configured tokens.
+///
+/// A request without an `Authorization: Bearer <token>` header is rejected
+/// as unauthorized. A request presenting an unknown or revoked token, a token
+/// which can't access the requested team, or a read-only token on an upload
+/// or a report of cache events is rejected as forbidden.
+pub async fn auth(req: Request<Body>) -> Result<Request<Body>, ServerError> {
+    let state = req.data::<State>().unwrap();
+
+    let token = bearer_token(&req).ok_or(ServerError::MissingToken)?;
+    let grant = state
+        .auth
+        .authenticate(token)
+        .ok_or(ServerError::InvalidToken)?;
+
+    let team_id = RequestContext::team_id(&req)?;
+    if !grant.teams.contains(team_id.as_str()) {
+        return Err(ServerError::TeamForbidden(team_id.to_string()));
+    }
+
+    let required = if req.method() == Method::PUT || req.uri().path() == "/v8/artifacts/events" {
+        Scope::ReadWrite
+    } else {
+        Scope::Read
+    };
+
+    if !grant.scope.allows(required) {
+        return Err(ServerError::InsufficientScope);
+    }
+
+    Ok(req)
+}
+
+fn bearer_token(req: &Request<Body>) -> Option<&str> {
+    let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = header.split_once(' ')?;
+
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    let token = token.trim();
+    if token.is_empty() {
+        return None;
+    }
+
+    Some(token)
+}
+
+fn digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Body, StatusCode};
+
+    use crate::{
+        test_util::{request, send, server},
+        TokenRegistry, TurborepoServer,
+    };
+
+    const ARTIFACT: &str = "/v8/artifacts/0123456789abcdef?teamId=acme";
+
+    async fn registry_server() -> TurborepoServer {
+        let registry = TokenRegistry::from_slice(
+            br#"{
+                "tokens": [
+                    { "token": "writer", "teams": ["acme"], "scope": "read-write" },
+                    { "token": "reader", "teams": ["acme"], "scope": "read" },
+                    { "token": "other", "teams": ["web"], "scope": "read-write" },
+                    { "token": "revoked", "teams": ["acme"], "scope": "read-write", "revoked": true }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        server(|builder| {
+            builder.with_token_registry(registry);
+        })
+        .await
+    }
+
+    async fn status(server: &TurborepoServer, method: &str, token: Option<&str>) -> StatusCode {
+        let req = request(method, ARTIFACT, token)
+            .body(Body::from("artifact"))
+            .unwrap();
+
+        send(server, req).await.status()
+    }
+
+    #[tokio::test]
+    async fn read_tokens_can_download_but_not_upload() {
+        let server = registry_server().await;
+
+        assert_eq!(
+            status(&server, "PUT", Some("reader")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(status(&server, "PUT", Some("writer")).await, StatusCode::OK);
+        assert_eq!(status(&server, "GET", Some("reader")).await, StatusCode::OK);
+        assert_eq!(
+            status(&server, "HEAD", Some("reader")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn read_tokens_can_not_report_cache_events() {
+        let server = registry_server().await;
+        let events = |token| {
+            request("POST", "/v8/artifacts/events?teamId=acme", Some(token))
+                .body(Body::from("[]"))
+                .unwrap()
+        };
+
+        let res = send(&server, events("reader")).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = send(&server, events("writer")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_unknown_and_revoked_tokens() {
+        let server = registry_server().await;
+
+        assert_eq!(status(&server, "GET", None).await, StatusCode::UNAUTHORIZED);
+        for token in ["unknown", "revoked"] {
+            assert_eq!(
+                status(&server, "GET", Some(token)).await,
+                StatusCode::FORBIDDEN,
+                "{}",
+                token
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_of_other_teams() {
+        let server = registry_server().await;
+
+        assert_eq!(
+            status(&server, "GET", Some("other")).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn the_shared_token_grants_every_team() {
+        let server = server(|builder| {
+            builder.with_token("shared".into());
+        })
+        .await;
+
+        assert_eq!(status(&server, "PUT", Some("shared")).await, StatusCode::OK);
+        assert_eq!(
+            status(&server, "PUT", Some("shared2")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(&server, "PUT", Some("")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use super::{digest, Grant, Scope, Teams};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenRegistryError {
+    #[error("can't read token file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("can't parse token file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("invalid token entry #{index}: {reason}")]
+    InvalidEntry { index: usize, reason: String },
+}
+
+/// Set of tokens, each restricted to a list of teams and to a scope.
+///
+/// The registry is loaded from a JSON file such as:
+///
+/// ```json
+/// {
+///   "tokens": [
+///     { "token": "ci-secret", "teams": ["acme"], "scope": "read-write" },
+///     { "sha256": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08", "teams": ["acme", "web"], "scope": "read" },
+///     { "token": "leaked-secret", "teams": ["acme"], "scope": "read-write", "revoked": true }
+///   ]
+/// }
+/// ```
+///
+/// Entries either hold the token in clear or its hex-encoded SHA-256 digest,
+/// so the file doesn't need to contain the secrets themselves. Revoked
+/// entries are kept in the file for the record, but their token is rejected.
+pub struct TokenRegistry {
+    entries: Vec<TokenEntry>,
+}
+
+struct TokenEntry {
+    digest: [u8; 32],
+    teams: Vec<String>,
+    scope: Scope,
+    revoked: bool,
+}
+
+#[derive(Deserialize)]
+struct TokenFile {
+    tokens: Vec<TokenFileEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TokenFileEntry {
+    token: Option<String>,
+    sha256: Option<String>,
+    teams: Vec<String>,
+    scope: Scope,
+    #[serde(default)]
+    revoked: bool,
+}
+
+impl TokenRegistry {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<TokenRegistry, TokenRegistryError> {
+        let content = std::fs::read(path)?;
+
+        Self::from_slice(&content)
+    }
+
+    pub fn from_slice(content: &[u8]) -> Result<TokenRegistry, TokenRegistryError> {
+        let file: TokenFile = serde_json::from_slice(content)?;
+
+        let entries = file
+            .tokens
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let invalid = |reason: &str| TokenRegistryError::InvalidEntry {
+                    index,
+                    reason: reason.into(),
+                };
+
+                let digest = match (entry.token, entry.sha256) {
+                    (Some(token), None) => digest(&token),
+                    (None, Some(sha256)) => hex::decode(sha256.trim())
+                        .ok()
+                        .and_then(|digest| digest.try_into().ok())
+                        .ok_or_else(|| invalid("`sha256` must be a hex-encoded SHA-256 digest"))?,
+                    _ => return Err(invalid("exactly one of `token` or `sha256` must be set")),
+                };
+
+                if entry.teams.is_empty() {
+                    return Err(invalid("`teams` can't be empty"));
+                }
+
+                Ok(TokenEntry {
+                    digest,
+                    teams: entry.teams,
+                    scope: entry.scope,
+                    revoked: entry.revoked,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(TokenRegistry { entries })
+    }
+
+    /// Looks up the grant of `token`.
+    ///
+    /// Every entry is compared, in constant time, against the digest of the
+    /// presented token. A revoked token has no grant.
+    pub(crate) fn authenticate(&self, token: &str) -> Option<Grant> {
+        let presented = digest(token);
+
+        self.entries
+            .iter()
+            .fold(None, |grant, entry| {
+                if bool::from(entry.digest.ct_eq(&presented)) {
+                    grant.or(Some(entry))
+                } else {
+                    grant
+                }
+            })
+            .filter(|entry| !entry.revoked)
+            .map(|entry| Grant {
+                teams: Teams::Only(entry.teams.clone()),
+                scope: entry.scope,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKENS: &str = r#"{
+        "tokens": [
+            { "token": "writer", "teams": ["acme"], "scope": "read-write" },
+            { "sha256": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08", "teams": ["acme", "web"], "scope": "read" },
+            { "token": "revoked", "teams": ["acme"], "scope": "read-write", "revoked": true }
+        ]
+    }"#;
+
+    #[test]
+    fn grants_the_scope_and_teams_of_the_entry() {
+        let registry = TokenRegistry::from_slice(TOKENS.as_bytes()).unwrap();
+
+        let grant = registry.authenticate("writer").unwrap();
+        assert_eq!(grant.scope, Scope::ReadWrite);
+        assert!(grant.teams.contains("acme"));
+        assert!(!grant.teams.contains("web"));
+
+        // The digest of "test".
+        let grant = registry.authenticate("test").unwrap();
+        assert_eq!(grant.scope, Scope::Read);
+        assert!(grant.teams.contains("web"));
+    }
+
+    #[test]
+    fn rejects_unknown_and_revoked_tokens() {
+        let registry = TokenRegistry::from_slice(TOKENS.as_bytes()).unwrap();
+
+        assert!(registry.authenticate("unknown").is_none());
+        assert!(registry.authenticate("revoked").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        for tokens in [
+            r#"{ "tokens": [{ "teams": ["acme"], "scope": "read" }] }"#,
+            r#"{ "tokens": [{ "token": "a", "sha256": "00", "teams": ["acme"], "scope": "read" }] }"#,
+            r#"{ "tokens": [{ "sha256": "not hex", "teams": ["acme"], "scope": "read" }] }"#,
+            r#"{ "tokens": [{ "token": "a", "teams": [], "scope": "read" }] }"#,
+        ] {
+            assert!(matches!(
+                TokenRegistry::from_slice(tokens.as_bytes()),
+                Err(TokenRegistryError::InvalidEntry { index: 0, .. })
+            ));
+        }
+    }
+}
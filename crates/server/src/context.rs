@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use hyper::{Body, Request};
+use routerify::prelude::*;
+use turborepo_core::{ArtifactId, ArtifactKey, TeamId, TurborepoError};
+use url::form_urlencoded;
+
+use crate::error::ServerError;
+
+/// Parameters every artifact route is called with.
+///
+/// The team comes from the `slug` query parameter or, failing that, from
+/// `teamId`. The artifact is the `:id` path parameter of the
+/// `/v8/artifacts/:id` routes. Both are validated, so invalid ids are
+/// rejected before reaching the storage.
+pub struct RequestContext {
+    pub team_id: TeamId,
+    /// The `teamId` query parameter, which `turbo` signs artifacts with even
+    /// when it addresses the team by its slug.
+    pub signing_team_id: Option<String>,
+    artifact_id: Option<ArtifactId>,
+}
+
+impl RequestContext {
+    /// Extracts the context of a request from a route handler.
+    pub fn from_request(req: &Request<Body>) -> Result<RequestContext, ServerError> {
+        Ok(RequestContext {
+            team_id: Self::team_id(req)?,
+            signing_team_id: query(req).remove("teamId"),
+            artifact_id: req
+                .param("id")
+                .map(|artifact_id| artifact_id.parse())
+                .transpose()
+                .map_err(TurborepoError::from)?,
+        })
+    }
+
+    /// Extracts the team of a request. Unlike
+    /// [`from_request`](Self::from_request), it can be called from
+    /// pre-middlewares, before route parameters are known.
+    pub fn team_id(req: &Request<Body>) -> Result<TeamId, ServerError> {
+        let mut query = query(req);
+
+        query
+            .remove("slug")
+            .filter(|team_id| !team_id.is_empty())
+            .or_else(|| query.remove("teamId"))
+            .filter(|team_id| !team_id.is_empty())
+            .ok_or(ServerError::MissingTeam)?
+            .parse()
+            .map_err(|err| TurborepoError::from(err).into())
+    }
+
+    /// The artifact of a `/v8/artifacts/:id` route.
+    pub fn artifact_key(&self) -> ArtifactKey {
+        let artifact_id = self
+            .artifact_id
+            .clone()
+            .expect("artifact routes have an `:id` parameter");
+
+        ArtifactKey::new(self.team_id.clone(), artifact_id)
+    }
+}
+
+fn query(req: &Request<Body>) -> HashMap<String, String> {
+    form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect()
+}
@@ -0,0 +1,99 @@
+use hyper::{header::CONTENT_TYPE, Body, Response, StatusCode};
+use serde_json::json;
+use turborepo_core::{StorageAdapterError, TurborepoError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    #[error("missing bearer token")]
+    MissingToken,
+    #[error("invalid bearer token")]
+    InvalidToken,
+    #[error("token can't access team `{0}`")]
+    TeamForbidden(String),
+    #[error("token is not allowed to upload artifacts")]
+    InsufficientScope,
+    #[error("missing `teamId` or `slug` query parameter")]
+    MissingTeam,
+    #[error("invalid request body: {0}")]
+    InvalidBody(String),
+    #[error("request body is larger than {0} bytes")]
+    BodyTooLarge(usize),
+    #[error(transparent)]
+    Core(#[from] TurborepoError),
+}
+
+impl From<hyper::Error> for ServerError {
+    fn from(value: hyper::Error) -> Self {
+        ServerError::InvalidBody(value.to_string())
+    }
+}
+
+impl From<serde_json::Error> for ServerError {
+    fn from(value: serde_json::Error) -> Self {
+        ServerError::InvalidBody(value.to_string())
+    }
+}
+
+impl ServerError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ServerError::MissingToken => StatusCode::UNAUTHORIZED,
+            ServerError::InvalidToken
+            | ServerError::TeamForbidden(_)
+            | ServerError::InsufficientScope => StatusCode::FORBIDDEN,
+            ServerError::MissingTeam | ServerError::InvalidBody(_) => StatusCode::BAD_REQUEST,
+            ServerError::BodyTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ServerError::Core(err) => match err {
+                TurborepoError::NotFound => StatusCode::NOT_FOUND,
+                TurborepoError::InvalidKey(_)
+                | TurborepoError::InvalidSignature
+                | TurborepoError::Body(_) => StatusCode::BAD_REQUEST,
+                TurborepoError::StorageAdapter(err) => match err {
+                    StorageAdapterError::NotFound(_) => StatusCode::NOT_FOUND,
+                    StorageAdapterError::InvalidKey(_) => StatusCode::BAD_REQUEST,
+                    StorageAdapterError::QuotaExceeded(_) => StatusCode::PAYLOAD_TOO_LARGE,
+                    StorageAdapterError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+                    StorageAdapterError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+                    StorageAdapterError::Unknown
+                    | StorageAdapterError::PermissionDenied(_)
+                    | StorageAdapterError::Io(_)
+                    | StorageAdapterError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                },
+                TurborepoError::Unknown | TurborepoError::Analytics(_) => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            },
+        }
+    }
+
+    /// Renders the error as a JSON body, `{"error": {"code", "message"}}`.
+    ///
+    /// The details of server-side failures are logged rather than sent back.
+    pub fn to_response(&self) -> Response<Body> {
+        let status = self.status();
+
+        let message = if status.is_server_error() {
+            eprintln!("{}", self);
+            status.canonical_reason().unwrap_or_default().to_lowercase()
+        } else {
+            self.to_string()
+        };
+
+        let body = json!({
+            "error": {
+                "code": status
+                    .canonical_reason()
+                    .unwrap_or_default()
+                    .to_lowercase()
+                    .replace(' ', "_"),
+                "message": message,
+            }
+        });
+
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+}
@@ -1,64 +1,173 @@
-use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
-
-use hyper::{Body, Request, Response, Server as HyperServer, StatusCode};
+mod auth;
+mod context;
+mod error;
+#[cfg(test)]
+mod test_util;
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use http_body::{LengthLimitError, Limited};
+use hyper::{
+    header::{AsHeaderName, CONTENT_LENGTH, CONTENT_TYPE, LAST_MODIFIED},
+    http::response,
+    Body, Request, Response, Server as HyperServer, StatusCode,
+};
 use routerify::{prelude::*, Middleware, RequestInfo, Router, RouterService};
-use turborepo_core::TurborepoCore;
-use url::form_urlencoded;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::net::lookup_host;
+use turborepo_core::{
+    analytics::CacheEvent, ArtifactMetadata, ArtifactStatus, ArtifactUpload, TurborepoCore,
+    TurborepoError,
+};
+
+use crate::{auth::Authenticator, context::RequestContext};
+pub use crate::{
+    auth::{JwtVerifier, JwtVerifierBuilder, JwtVerifierError, TokenRegistry, TokenRegistryError},
+    error::ServerError,
+};
 
 #[derive(Clone)]
 pub struct State {
     core: Arc<TurborepoCore>,
+    auth: Arc<Authenticator>,
 }
 
+/// Header carrying the signature of an artifact, see
+/// [`turborepo_core::signature`].
+const ARTIFACT_TAG: &str = "x-artifact-tag";
+/// Header carrying the time, in milliseconds, the task producing an artifact
+/// took to run.
+const ARTIFACT_DURATION: &str = "x-artifact-duration";
+/// Size, in bytes, above which the JSON bodies of requests are rejected.
+const MAX_JSON_BODY_SIZE: usize = 1024 * 1024;
+
 fn empty() -> Body {
     Body::empty()
 }
 
-fn router(core: &Arc<TurborepoCore>) -> Router<Body, Infallible> {
+fn header(req: &Request<Body>, name: impl AsHeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+/// Reads and parses the JSON body of a request, of at most
+/// [`MAX_JSON_BODY_SIZE`] bytes.
+async fn json_body<T: DeserializeOwned>(req: Request<Body>) -> Result<T, ServerError> {
+    let body = hyper::body::to_bytes(Limited::new(req.into_body(), MAX_JSON_BODY_SIZE))
+        .await
+        .map_err(|err| match err.downcast::<LengthLimitError>() {
+            Ok(_) => ServerError::BodyTooLarge(MAX_JSON_BODY_SIZE),
+            Err(err) => ServerError::InvalidBody(err.to_string()),
+        })?;
+
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Adds the headers describing an artifact, but its `Content-Length`, to a
+/// GET or HEAD response.
+fn with_artifact_metadata(
+    mut builder: response::Builder,
+    metadata: Option<&ArtifactMetadata>,
+) -> response::Builder {
+    let Some(metadata) = metadata else {
+        return builder.header(CONTENT_TYPE, "application/octet-stream");
+    };
+
+    builder = builder
+        .header(
+            CONTENT_TYPE,
+            metadata
+                .content_type
+                .as_deref()
+                .unwrap_or("application/octet-stream"),
+        )
+        .header(LAST_MODIFIED, httpdate::fmt_http_date(metadata.uploaded_at));
+
+    if let Some(duration) = metadata.duration {
+        builder = builder.header(ARTIFACT_DURATION, duration);
+    }
+
+    if let Some(tag) = &metadata.tag {
+        builder = builder.header(ARTIFACT_TAG, tag);
+    }
+
+    builder
+}
+
+fn router(core: &Arc<TurborepoCore>, auth: &Arc<Authenticator>) -> Router<Body, ServerError> {
     Router::builder()
-        .data(State { core: core.clone() })
+        .data(State {
+            core: core.clone(),
+            auth: auth.clone(),
+        })
         .middleware(Middleware::pre(logger))
+        .middleware(Middleware::pre_with_path("/v8/artifacts*", auth::auth).unwrap())
         .head("/v8/artifacts/:id", head)
         .get("/v8/artifacts/:id", get)
         .put("/v8/artifacts/:id", put)
+        .post("/v8/artifacts", query)
         .post("/v8/artifacts/events", events)
         .err_handler_with_info(error_handler)
         .build()
         .unwrap()
 }
 
-// A handler for "/" page.
-async fn head(req: Request<Body>) -> Result<Response<Body>, Infallible> {
-    let artifact_id = req.param("id").unwrap();
+async fn head(req: Request<Body>) -> Result<Response<Body>, ServerError> {
     let state = req.data::<State>().unwrap();
-    let query = form_urlencoded::parse(req.uri().query().unwrap().as_bytes())
-        .into_owned()
-        .collect::<HashMap<String, String>>();
+    let context = RequestContext::from_request(&req)?;
+
+    let key = context.artifact_key();
 
-    if query.get("slug".into()).is_none() && query.get("teamId".into()).is_none() {
-        return Ok(Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(empty())
-            .unwrap());
+    let info = state
+        .core
+        .head_cached_artifact(&key)
+        .await?
+        .ok_or(TurborepoError::NotFound)?;
+
+    let mut builder = with_artifact_metadata(Response::builder(), info.metadata.as_ref())
+        .header(CONTENT_LENGTH, info.size);
+    if let (None, Some(last_modified)) = (&info.metadata, info.last_modified) {
+        builder = builder.header(LAST_MODIFIED, httpdate::fmt_http_date(last_modified));
     }
 
-    let team_id = query
-        .get("slug".into())
-        .or_else(|| query.get("teamId".into()))
-        .unwrap();
+    Ok(builder.status(StatusCode::OK).body(empty()).unwrap())
+}
 
-    let exists = state
+async fn get(req: Request<Body>) -> Result<Response<Body>, ServerError> {
+    let state = req.data::<State>().unwrap();
+    let context = RequestContext::from_request(&req)?;
+
+    let artifact = state
         .core
-        .exists_cached_artifact(artifact_id, team_id)
-        .await
-        .unwrap();
+        .get_cached_artifact(&context.artifact_key())
+        .await?;
 
-    if !exists {
-        return Ok(Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(empty())
-            .unwrap());
-    }
+    Ok(
+        with_artifact_metadata(Response::builder(), artifact.metadata.as_ref())
+            .header(CONTENT_LENGTH, artifact.size)
+            .status(StatusCode::OK)
+            .body(artifact.body)
+            .unwrap(),
+    )
+}
+
+async fn put(req: Request<Body>) -> Result<Response<Body>, ServerError> {
+    let state = req.data::<State>().unwrap().clone();
+    let context = RequestContext::from_request(&req)?;
+
+    let upload = ArtifactUpload {
+        signing_team_id: context.signing_team_id.clone(),
+        duration: header(&req, ARTIFACT_DURATION).and_then(|duration| duration.parse().ok()),
+        tag: header(&req, ARTIFACT_TAG),
+        content_type: header(&req, CONTENT_TYPE),
+    };
+
+    state
+        .core
+        .create_cached_artifact(&context.artifact_key(), req.into_body(), upload)
+        .await?;
 
     Ok(Response::builder()
         .status(StatusCode::OK)
@@ -66,93 +175,83 @@ async fn head(req: Request<Body>) -> Result<Response<Body>, Infallible> {
         .unwrap())
 }
 
-// A handler for "/" page.
-async fn get(req: Request<Body>) -> Result<Response<Body>, Infallible> {
-    let artifact_id = req.param("id").unwrap();
-    let state = req.data::<State>().unwrap();
-    let query = form_urlencoded::parse(req.uri().query().unwrap().as_bytes())
-        .into_owned()
-        .collect::<HashMap<String, String>>();
-
-    if query.get("slug".into()).is_none() && query.get("teamId".into()).is_none() {
-        return Ok(Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(empty())
-            .unwrap());
-    }
-
-    let team_id = query
-        .get("slug".into())
-        .or_else(|| query.get("teamId".into()))
-        .unwrap();
+#[derive(Deserialize)]
+struct QueryRequest {
+    hashes: Vec<String>,
+}
 
-    match state
-        .core
-        .get_cached_artifact(artifact_id.to_string(), team_id.to_owned())
-        .await
-    {
-        Ok(artifact) => Ok(Response::builder()
-            .status(StatusCode::OK)
-            .body(Body::from(artifact))
-            .unwrap()),
-        Err(_) => Ok(Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(empty())
-            .unwrap()),
-    }
+#[derive(Serialize)]
+#[serde(untagged)]
+enum QueryResponse {
+    Found {
+        size: u64,
+        #[serde(rename = "taskDurationMs")]
+        task_duration_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+    },
+    Error {
+        error: QueryError,
+    },
 }
 
-async fn put(req: Request<Body>) -> Result<Response<Body>, Infallible> {
-    let artifact_id = req.param("id").unwrap().clone();
-    let state = req.data::<State>().to_owned().unwrap().clone();
-    let query = form_urlencoded::parse(req.uri().query().unwrap().as_bytes())
-        .into_owned()
-        .collect::<HashMap<String, String>>();
+#[derive(Serialize)]
+struct QueryError {
+    message: String,
+}
 
-    if query.get("slug".into()).is_none() && query.get("teamId".into()).is_none() {
-        return Ok(Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(empty())
-            .unwrap());
-    }
+/// Tells, for each of a list of hashes, whether the artifact exists and, if
+/// so, its size, duration and tag. Missing artifacts are reported as `null`.
+async fn query(req: Request<Body>) -> Result<Response<Body>, ServerError> {
+    let state = req.data::<State>().unwrap().clone();
+    let context = RequestContext::from_request(&req)?;
 
-    let team_id = query
-        .get("slug".into())
-        .or_else(|| query.get("teamId".into()))
-        .unwrap();
+    let request = json_body::<QueryRequest>(req).await?;
 
-    state
+    let statuses = state
         .core
-        .create_cached_artifact(artifact_id.to_string(), team_id.to_owned(), req.into_body())
+        .query_cached_artifacts(request.hashes, &context.team_id)
         .await
-        .unwrap();
+        .into_iter()
+        .map(|(artifact_id, status)| {
+            let response = match status {
+                Ok(ArtifactStatus::Missing) => None,
+                Ok(ArtifactStatus::Found { size, metadata }) => Some(QueryResponse::Found {
+                    size,
+                    task_duration_ms: metadata
+                        .as_ref()
+                        .and_then(|metadata| metadata.duration)
+                        .unwrap_or(0),
+                    tag: metadata.and_then(|metadata| metadata.tag),
+                }),
+                Err(err) => Some(QueryResponse::Error {
+                    error: QueryError {
+                        message: err.to_string(),
+                    },
+                }),
+            };
+
+            (artifact_id, response)
+        })
+        .collect::<HashMap<_, _>>();
 
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(empty())
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(&statuses)?))
         .unwrap())
 }
 
-async fn events(req: Request<Body>) -> Result<Response<Body>, Infallible> {
-    let query = form_urlencoded::parse(req.uri().query().unwrap().as_bytes())
-        .into_owned()
-        .collect::<HashMap<String, String>>();
+async fn events(req: Request<Body>) -> Result<Response<Body>, ServerError> {
+    let state = req.data::<State>().unwrap().clone();
+    let context = RequestContext::from_request(&req)?;
 
-    if query.get("slug".into()).is_none() && query.get("teamId".into()).is_none() {
-        return Ok(Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(empty())
-            .unwrap());
-    }
-
-    let _team_id = query
-        .get("slug".into())
-        .or_else(|| query.get("teamId".into()))
-        .unwrap();
-
-    let body = { hyper::body::to_bytes(req.into_body()).await.unwrap() };
+    let events = json_body::<Vec<CacheEvent>>(req).await?;
 
-    println!("{}", String::from_utf8(body.to_vec()).unwrap());
+    state
+        .core
+        .record_cache_events(&context.team_id, events)
+        .await?;
 
     Ok(Response::builder()
         .status(StatusCode::OK)
@@ -162,7 +261,9 @@ async fn events(req: Request<Body>) -> Result<Response<Body>, Infallible> {
 
 pub struct TurborepoServer {
     core: Arc<TurborepoCore>,
-    token: String,
+    auth: Arc<Authenticator>,
+    host: String,
+    port: u16,
 }
 
 impl TurborepoServer {
@@ -170,39 +271,90 @@ impl TurborepoServer {
         TurborepoServerBuilder {
             core: None,
             token: None,
+            token_registry: None,
+            jwt: None,
+            host: None,
+            port: None,
         }
     }
 
-    pub async fn listen(&self) -> std::io::Result<()>
-// where
-    //     A: ToSocketAddrs,
-    {
-        let addr: SocketAddr = ([127, 0, 0, 1], 3010).into();
-        let router = router(&self.core);
+    /// Binds every socket the configured address resolves to and serves the
+    /// cache API on all of them until one of the listeners fails.
+    pub async fn listen(&self) -> std::io::Result<()> {
+        let addrs = self.resolve().await?;
 
-        // Create a Service from the router above to handle incoming requests.
-        let service = RouterService::new(router).unwrap();
+        let servers = addrs
+            .iter()
+            .map(|addr| {
+                let router = router(&self.core, &self.auth);
 
-        let server = HyperServer::bind(&addr).serve(service);
+                // Create a Service from the router above to handle incoming requests.
+                let service = RouterService::new(router).unwrap();
 
-        if let Err(e) = server.await {
+                let server = HyperServer::try_bind(addr)
+                    .map_err(|e| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::AddrNotAvailable,
+                            format!("can't bind {}: {}", addr, e),
+                        )
+                    })?
+                    .serve(service);
+
+                println!("listening on http://{}", addr);
+
+                Ok(server)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        if let Err(e) = futures::future::try_join_all(servers).await {
             eprintln!("server error: {}", e);
         }
 
         Ok(())
     }
+
+    async fn resolve(&self) -> std::io::Result<Vec<SocketAddr>> {
+        // Accept bracketed IPv6 literals (`[::1]`) as well as bare ones.
+        let host = self
+            .host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(&self.host);
+
+        let mut addrs = Vec::new();
+        for addr in lookup_host((host, self.port)).await? {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+
+        if addrs.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrNotAvailable,
+                format!("{} does not resolve to any address", self.host),
+            ));
+        }
+
+        Ok(addrs)
+    }
 }
 
 pub struct TurborepoServerBuilder {
     core: Option<TurborepoCore>,
     token: Option<String>,
+    token_registry: Option<TokenRegistry>,
+    jwt: Option<JwtVerifier>,
+    host: Option<String>,
+    port: Option<u16>,
 }
 
 impl TurborepoServerBuilder {
     pub fn build(&mut self) -> TurborepoServer {
         TurborepoServer {
             core: Arc::new(self.core.take().expect("can't build without storage")),
-            token: self.token.take().expect("can't build without storage"),
+            auth: Arc::new(self.authenticator()),
+            host: self.host.take().unwrap_or_else(|| "127.0.0.1".into()),
+            port: self.port.take().unwrap_or(3010),
         }
     }
 
@@ -217,9 +369,47 @@ impl TurborepoServerBuilder {
 
         self
     }
+
+    /// Accepts the tokens of `registry`, each limited to its own teams and
+    /// scope. It can be combined with [`with_token`](Self::with_token).
+    pub fn with_token_registry(&mut self, registry: TokenRegistry) -> &mut TurborepoServerBuilder {
+        self.token_registry = Some(registry);
+
+        self
+    }
+
+    /// Accepts JWTs validated by `verifier`, each limited to the teams named
+    /// by its team claim.
+    pub fn with_jwt_verifier(&mut self, verifier: JwtVerifier) -> &mut TurborepoServerBuilder {
+        self.jwt = Some(verifier);
+
+        self
+    }
+
+    /// Sets the address to listen on. `host` can be an IPv4 or IPv6 literal,
+    /// or a hostname; a hostname resolving to several addresses is bound on
+    /// each of them.
+    pub fn with_address(&mut self, host: String, port: u16) -> &mut TurborepoServerBuilder {
+        self.host = Some(host);
+        self.port = Some(port);
+
+        self
+    }
+
+    fn authenticator(&mut self) -> Authenticator {
+        let token = self.token.take();
+        let registry = self.token_registry.take();
+        let jwt = self.jwt.take();
+
+        if token.is_none() && registry.is_none() && jwt.is_none() {
+            panic!("can't build without token");
+        }
+
+        Authenticator::new(token, registry, jwt)
+    }
 }
 
-async fn logger(req: Request<Body>) -> Result<Request<Body>, Infallible> {
+async fn logger(req: Request<Body>) -> Result<Request<Body>, ServerError> {
     println!(
         "{} {} {}",
         req.remote_addr(),
@@ -231,9 +421,232 @@ async fn logger(req: Request<Body>) -> Result<Request<Body>, Infallible> {
 }
 
 async fn error_handler(err: routerify::RouteError, _: RequestInfo) -> Response<Body> {
+    if let Some(err) = err.downcast_ref::<ServerError>() {
+        return err.to_response();
+    }
+
     eprintln!("{}", err);
-    Response::builder()
-        .status(StatusCode::INTERNAL_SERVER_ERROR)
-        .body(Body::from(format!("Something went wrong: {}", err)))
-        .unwrap()
+    ServerError::Core(TurborepoError::Unknown).to_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use hyper::{header::CONTENT_LENGTH, Body, StatusCode};
+    use serde_json::json;
+    use turborepo_core::{signature, TurborepoCore};
+    use turborepo_memory_storage_adapter::MemoryStorageAdapter;
+    use turborepo_storage_adapter::StorageAdapter;
+
+    use crate::{
+        test_util::{request, send, server},
+        TurborepoServer, ARTIFACT_TAG, MAX_JSON_BODY_SIZE,
+    };
+
+    #[tokio::test]
+    async fn verifies_signatures_with_the_team_id_of_slug_requests() {
+        let core = TurborepoCore::builder()
+            .with_storage(Arc::new(MemoryStorageAdapter::builder().build()))
+            .with_signing_key("acme".into(), b"key".to_vec())
+            .build()
+            .await
+            .unwrap();
+        let server = TurborepoServer::builder()
+            .with_core(core)
+            .with_token("token".into())
+            .build();
+
+        let upload = |uri: &str, tag: String| {
+            request("PUT", uri, Some("token"))
+                .header(ARTIFACT_TAG, tag)
+                .body(Body::from("artifact"))
+                .unwrap()
+        };
+
+        let by_slug = "/v8/artifacts/0123abcd?teamId=team_1234&slug=acme";
+        let tag = signature::tag(b"key", "0123abcd", "team_1234", b"artifact");
+        let res = send(&server, upload(by_slug, tag)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let tag = signature::tag(b"key", "0123abcd", "acme", b"artifact");
+        let res = send(&server, upload(by_slug, tag)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // `turbo` signs with an empty team id when it only knows the slug.
+        let tag = signature::tag(b"key", "0123abcd", "", b"artifact");
+        let res = send(&server, upload("/v8/artifacts/0123abcd?slug=acme", tag)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let tag = signature::tag(b"key", "0123abcd", "acme", b"artifact");
+        let res = send(&server, upload("/v8/artifacts/0123abcd?teamId=acme", tag)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn stores_signed_artifacts_streamed_in_chunks() {
+        let core = TurborepoCore::builder()
+            .with_storage(Arc::new(MemoryStorageAdapter::builder().build()))
+            .with_signing_key("acme".into(), b"key".to_vec())
+            .build()
+            .await
+            .unwrap();
+        let server = TurborepoServer::builder()
+            .with_core(core)
+            .with_token("token".into())
+            .build();
+        let uri = "/v8/artifacts/0123abcd?teamId=acme";
+
+        let chunks = ["first ", "second ", "third"];
+        let tag = signature::tag(b"key", "0123abcd", "acme", chunks.concat().as_bytes());
+        let body = Body::wrap_stream(futures::stream::iter(chunks.map(Ok::<_, std::io::Error>)));
+        let req = request("PUT", uri, Some("token"))
+            .header(ARTIFACT_TAG, tag)
+            .body(body)
+            .unwrap();
+        assert_eq!(send(&server, req).await.status(), StatusCode::OK);
+
+        let req = request("GET", uri, Some("token"))
+            .body(Body::empty())
+            .unwrap();
+        let res = send(&server, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[CONTENT_LENGTH], "18");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, chunks.concat());
+    }
+
+    #[tokio::test]
+    async fn drops_the_tag_of_replaced_artifacts() {
+        let core = TurborepoCore::builder()
+            .with_storage(Arc::new(MemoryStorageAdapter::builder().build()))
+            .build()
+            .await
+            .unwrap();
+        let server = TurborepoServer::builder()
+            .with_core(core)
+            .with_token("token".into())
+            .build();
+        let uri = "/v8/artifacts/0123abcd?teamId=acme";
+
+        let req = request("PUT", uri, Some("token"))
+            .header(ARTIFACT_TAG, "first")
+            .body(Body::from("first"))
+            .unwrap();
+        assert_eq!(send(&server, req).await.status(), StatusCode::OK);
+
+        let req = request("PUT", uri, Some("token"))
+            .body(Body::from("second artifact"))
+            .unwrap();
+        assert_eq!(send(&server, req).await.status(), StatusCode::OK);
+
+        for method in ["HEAD", "GET"] {
+            let req = request(method, uri, Some("token"))
+                .body(Body::empty())
+                .unwrap();
+            let res = send(&server, req).await;
+
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(res.headers().get(ARTIFACT_TAG).is_none(), "{}", method);
+            assert_eq!(res.headers()[CONTENT_LENGTH], "15", "{}", method);
+        }
+    }
+
+    #[tokio::test]
+    async fn queries_report_the_size_of_artifacts_without_metadata() {
+        let storage = Arc::new(MemoryStorageAdapter::builder().build());
+        storage
+            .upload("acme/0123abcd".into(), Bytes::from("artifact"))
+            .await
+            .unwrap();
+        let core = TurborepoCore::builder()
+            .with_storage(storage)
+            .build()
+            .await
+            .unwrap();
+        let server = TurborepoServer::builder()
+            .with_core(core)
+            .with_token("token".into())
+            .build();
+
+        let req = request("POST", "/v8/artifacts?teamId=acme", Some("token"))
+            .body(Body::from(
+                json!({ "hashes": ["0123abcd", "4567ef"] }).to_string(),
+            ))
+            .unwrap();
+        let res = send(&server, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({
+                "0123abcd": { "size": 8, "taskDurationMs": 0 },
+                "4567ef": null,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_queries() {
+        let server = server(|builder| {
+            builder.with_token("token".into());
+        })
+        .await;
+
+        let hashes = vec!["0123abcd"; MAX_JSON_BODY_SIZE / 8];
+        let req = request("POST", "/v8/artifacts?teamId=acme", Some("token"))
+            .body(Body::from(json!({ "hashes": hashes }).to_string()))
+            .unwrap();
+        let res = send(&server, req).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = request("POST", "/v8/artifacts?teamId=acme", Some("token"))
+            .body(Body::from(json!({ "hashes": ["0123abcd"] }).to_string()))
+            .unwrap();
+        let res = send(&server, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_cache_events() {
+        let server = server(|builder| {
+            builder.with_token("token".into());
+        })
+        .await;
+        let events = |body: String| {
+            request("POST", "/v8/artifacts/events?teamId=acme", Some("token"))
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let event = json!({
+            "sessionId": "3f3ac2c4",
+            "source": "REMOTE",
+            "event": "HIT",
+            "hash": "0123abcd",
+            "duration": 120,
+        });
+        let res = send(&server, events(json!([event]).to_string())).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut unknown_source = event.clone();
+        unknown_source["source"] = json!("CLOUD");
+        let mut missing_hash = event.clone();
+        missing_hash.as_object_mut().unwrap().remove("hash");
+
+        for body in [
+            "not json".to_string(),
+            event.to_string(),
+            json!([unknown_source]).to_string(),
+            json!([missing_hash]).to_string(),
+        ] {
+            let res = send(&server, events(body.clone())).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", body);
+        }
+
+        let res = send(&server, events(json!(vec![event; 20_000]).to_string())).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
 }
@@ -0,0 +1,48 @@
+//! Sends requests through the router of a server backed by the memory
+//! storage, without binding a socket.
+
+use std::sync::Arc;
+
+use hyper::{header::AUTHORIZATION, service::Service, Body, Request, Response};
+use routerify::RequestServiceBuilder;
+use turborepo_core::TurborepoCore;
+use turborepo_memory_storage_adapter::MemoryStorageAdapter;
+
+use crate::{router, TurborepoServer, TurborepoServerBuilder};
+
+/// Builds a server, configured by `configure`, on an empty memory storage.
+pub(crate) async fn server(configure: impl FnOnce(&mut TurborepoServerBuilder)) -> TurborepoServer {
+    let core = TurborepoCore::builder()
+        .with_storage(Arc::new(MemoryStorageAdapter::builder().build()))
+        .build()
+        .await
+        .unwrap();
+
+    let mut builder = TurborepoServer::builder();
+    builder.with_core(core);
+    configure(&mut builder);
+
+    builder.build()
+}
+
+/// Starts a request to `uri`, authenticated with `token` if any.
+pub(crate) fn request(
+    method: &str,
+    uri: &str,
+    token: Option<&str>,
+) -> hyper::http::request::Builder {
+    let builder = Request::builder().method(method).uri(uri);
+
+    match token {
+        Some(token) => builder.header(AUTHORIZATION, format!("Bearer {}", token)),
+        None => builder,
+    }
+}
+
+pub(crate) async fn send(server: &TurborepoServer, req: Request<Body>) -> Response<Body> {
+    let mut service = RequestServiceBuilder::new(router(&server.core, &server.auth))
+        .unwrap()
+        .build(([127, 0, 0, 1], 0).into());
+
+    service.call(req).await.unwrap()
+}
@@ -1,201 +1,528 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use async_trait::async_trait;
+use aws_config::default_provider::{
+    credentials::DefaultCredentialsChain, region::DefaultRegionChain,
+};
 use aws_sdk_s3::{
-    model::{CompletedMultipartUpload, CompletedPart},
-    output::CreateMultipartUploadOutput,
+    config::{Credentials, Region},
+    error::{ProvideErrorMetadata, SdkError},
+    operation::create_multipart_upload::CreateMultipartUploadOutput,
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart, Object},
     Client,
 };
-use aws_smithy_http::{body::SdkBody, byte_stream::ByteStream};
-use bytes::Bytes;
-use futures::StreamExt;
-use hyper::Body;
-use turborepo_storage_adapter::{StorageAdapter, StorageAdapterError};
+use aws_smithy_http::body::SdkBody;
+use bytes::{Bytes, BytesMut};
+use futures::{stream::Fuse, Stream, StreamExt, TryStreamExt};
+use hyper::{
+    http::uri::{InvalidUri, Uri},
+    Body, Response,
+};
+use turborepo_storage_adapter::{
+    is_metadata_path, metadata_path, ArtifactStream, ObjectEntries, ObjectEntry, ObjectInfo,
+    StorageAdapter, StorageAdapterError,
+};
+
+/// Smallest part S3 accepts in a multipart upload, but for the last one.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// Default size of the parts of a multipart upload.
+const PART_SIZE: usize = 8 * 1024 * 1024;
+/// Default number of parts uploaded at once.
+const UPLOAD_CONCURRENCY: usize = 4;
 
 pub struct AwsS3StorageAdapter {
     client: Client,
     bucket: String,
+    prefix: Option<String>,
+    part_size: usize,
+    upload_concurrency: usize,
 }
 
 impl AwsS3StorageAdapter {
     pub fn builder() -> AwsS3StorageAdapterBuilder {
-        AwsS3StorageAdapterBuilder { bucket: None }
+        AwsS3StorageAdapterBuilder {
+            bucket: None,
+            prefix: None,
+            endpoint: None,
+            force_path_style: None,
+            region: None,
+            credentials: None,
+            profile: None,
+            part_size: None,
+            upload_concurrency: None,
+        }
     }
 }
 
 impl AwsS3StorageAdapter {
-    async fn _exists(&self, path: &PathBuf) -> Result<(), StorageAdapterError> {
+    /// Key of the object stored at `path`, under the configured prefix.
+    fn key(&self, path: &Path) -> Result<String, StorageAdapterError> {
+        let path = path.to_str().ok_or_else(|| {
+            StorageAdapterError::InvalidKey(format!("{:?} is not UTF-8", path).into())
+        })?;
+
+        Ok(match &self.prefix {
+            Some(prefix) => format!("{}/{}", prefix, path),
+            None => path.to_string(),
+        })
+    }
+
+    /// Entry of an artifact listed by `ListObjectsV2`, with its path relative
+    /// to the prefix. Metadata sidecars have none.
+    fn entry(&self, object: &Object) -> Option<ObjectEntry> {
+        let key = object.key()?;
+        let path = match &self.prefix {
+            Some(prefix) => key.strip_prefix(prefix.as_str())?.strip_prefix('/')?,
+            None => key,
+        };
+
+        if is_metadata_path(Path::new(path)) {
+            return None;
+        }
+
+        Some(ObjectEntry {
+            path: path.into(),
+            info: ObjectInfo {
+                size: object.size().max(0) as u64,
+                last_modified: object
+                    .last_modified()
+                    .and_then(|last_modified| SystemTime::try_from(*last_modified).ok()),
+            },
+        })
+    }
+
+    async fn delete_object(&self, path: &Path) -> Result<(), StorageAdapterError> {
         self.client
-            .get_object()
+            .delete_object()
             .bucket(&self.bucket)
-            .key(path.to_str().unwrap())
+            .key(self.key(path)?)
             .send()
             .await
             .map(|_| ())
-            .map_err(|_| StorageAdapterError::Unknown)
+            .map_err(classify)
     }
-}
 
-#[async_trait]
-impl StorageAdapter for AwsS3StorageAdapter {
-    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
-        self._exists(&path).await?;
+    /// Uploads `artifact` with a single `PutObject` when it fits in one part,
+    /// or as a multipart upload otherwise.
+    async fn upload_stream<S, E>(&self, path: &Path, artifact: S) -> Result<(), StorageAdapterError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Unpin,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let key = self.key(path)?;
+        let mut artifact = artifact.fuse();
 
-        let object = self
-            .client
-            .get_object()
-            .bucket(&self.bucket)
-            .key(path.to_string_lossy())
-            .send()
-            .await
-            .unwrap();
+        let mut buffer = BytesMut::new();
+        let ended = fill(&mut artifact, &mut buffer, self.part_size).await?;
 
-        let inner = object.body.collect().await.unwrap();
-        Ok(inner.into_bytes())
-    }
+        if ended && buffer.len() <= self.part_size {
+            return self
+                .client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(&key)
+                .body(ByteStream::new(SdkBody::from(buffer.freeze())))
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(classify);
+        }
 
-    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
-        Ok(self
-            .client
-            .get_object()
-            .bucket(&self.bucket)
-            .key(path.to_str().unwrap())
-            .send()
+        self.multipart_upload(&key, parts(artifact, buffer, self.part_size))
             .await
-            .map(|_| true)
-            .unwrap_or(false))
     }
 
-    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+    /// Uploads `parts`, numbered from 1, running at most `upload_concurrency`
+    /// `UploadPart` requests at once. The upload is aborted if any of them,
+    /// or reading the parts, fails.
+    async fn multipart_upload(
+        &self,
+        key: &str,
+        parts: impl Stream<Item = Result<Bytes, StorageAdapterError>> + Send,
+    ) -> Result<(), StorageAdapterError> {
         let create_multipart_upload_output: CreateMultipartUploadOutput = self
             .client
             .create_multipart_upload()
             .bucket(&self.bucket)
-            .key(path.to_str().unwrap())
+            .key(key)
             .send()
             .await
-            .unwrap();
+            .map_err(classify)?;
+
+        let upload_id = create_multipart_upload_output
+            .upload_id()
+            .ok_or_else(|| StorageAdapterError::Other("S3 returned no upload id".into()))?;
+
+        let result = async {
+            let mut upload_parts = parts
+                .enumerate()
+                .map(|(index, part)| async move {
+                    self.upload_part(key, upload_id, index as i32 + 1, part?)
+                        .await
+                })
+                .buffer_unordered(self.upload_concurrency)
+                .try_collect::<Vec<_>>()
+                .await?;
+
+            upload_parts.sort_by_key(|part| part.part_number());
+
+            let completed_multipart_upload: CompletedMultipartUpload =
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(upload_parts))
+                    .build();
+
+            self.client
+                .complete_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .multipart_upload(completed_multipart_upload)
+                .upload_id(upload_id)
+                .send()
+                .await
+                .map_err(classify)?;
+
+            Ok(())
+        }
+        .await;
 
-        let upload_id = create_multipart_upload_output.upload_id().unwrap();
+        if result.is_err() {
+            if let Err(err) = self
+                .client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .send()
+                .await
+            {
+                eprintln!("can't abort upload of {}: {}", key, err);
+            }
+        }
 
-        let mut upload_parts = vec![];
+        result
+    }
 
-        let sdk_body = SdkBody::from(artifact);
-        let body = ByteStream::new(sdk_body);
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        part: Bytes,
+    ) -> Result<CompletedPart, StorageAdapterError> {
         let upload_part_res = self
             .client
             .upload_part()
             .bucket(&self.bucket)
-            .key(path.to_str().unwrap())
+            .key(key)
             .upload_id(upload_id)
-            .body(body)
-            .part_number(1)
+            .body(ByteStream::new(SdkBody::from(part)))
+            .part_number(part_number)
             .send()
             .await
-            .unwrap();
+            .map_err(classify)?;
 
-        upload_parts.push(
-            CompletedPart::builder()
-                .e_tag(upload_part_res.e_tag.unwrap_or_default())
-                .part_number(1)
-                .build(),
-        );
+        Ok(CompletedPart::builder()
+            .e_tag(upload_part_res.e_tag.unwrap_or_default())
+            .part_number(part_number)
+            .build())
+    }
+}
 
-        let completed_multipart_upload: CompletedMultipartUpload =
-            CompletedMultipartUpload::builder()
-                .set_parts(Some(upload_parts))
-                .build();
+#[async_trait]
+impl StorageAdapter for AwsS3StorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(&path)?)
+            .send()
+            .await
+            .map_err(classify)?;
+
+        let inner = object
+            .body
+            .collect()
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))?;
+        Ok(inner.into_bytes())
+    }
 
-        let _complete_multipart_upload_res = self
+    async fn get_(&self, path: PathBuf) -> Result<ArtifactStream, StorageAdapterError> {
+        let object = self
             .client
-            .complete_multipart_upload()
+            .get_object()
             .bucket(&self.bucket)
-            .key(path.to_str().unwrap())
-            .multipart_upload(completed_multipart_upload)
-            .upload_id(upload_id)
+            .key(self.key(&path)?)
             .send()
             .await
-            .unwrap();
+            .map_err(classify)?;
 
-        Ok(())
+        Ok(ArtifactStream {
+            size: object.content_length().max(0) as u64,
+            body: Body::wrap_stream(object.body),
+        })
     }
 
-    async fn upload_<'a>(
-        &self,
-        path: PathBuf,
-        mut artifact: Body,
-    ) -> Result<(), StorageAdapterError> {
-        let create_multipart_upload_output: CreateMultipartUploadOutput = self
+    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
+        Ok(self.stat(path).await?.is_some())
+    }
+
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        let object = match self
             .client
-            .create_multipart_upload()
+            .head_object()
             .bucket(&self.bucket)
-            .key(path.to_str().unwrap())
+            .key(self.key(&path)?)
             .send()
             .await
-            .unwrap();
+            .map_err(classify)
+        {
+            Ok(object) => object,
+            Err(err) if err.is_not_found() => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Some(ObjectInfo {
+            size: object.content_length().max(0) as u64,
+            last_modified: object
+                .last_modified()
+                .and_then(|last_modified| SystemTime::try_from(*last_modified).ok()),
+        }))
+    }
+
+    /// Lists the objects with `ListObjectsV2`, fetching the next page once
+    /// the previous one was consumed.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let prefix = match self.key(&prefix) {
+            Ok(prefix) => prefix,
+            Err(err) => return futures::stream::once(async { Err(err) }).boxed(),
+        };
 
-        let upload_id = create_multipart_upload_output.upload_id().unwrap();
+        futures::stream::try_unfold(Some(None), move |continuation_token| {
+            let prefix = prefix.clone();
 
-        let mut upload_parts = vec![];
+            async move {
+                let Some(continuation_token) = continuation_token else {
+                    return Ok(None);
+                };
 
-        while let Some(chunk) = artifact.next().await {
-            if let Err(_) = chunk {
-                continue;
+                let page = self
+                    .client
+                    .list_objects_v2()
+                    .bucket(&self.bucket)
+                    .prefix(prefix)
+                    .set_continuation_token(continuation_token)
+                    .send()
+                    .await
+                    .map_err(classify)?;
+
+                let entries = page
+                    .contents()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|object| self.entry(object))
+                    .map(Ok)
+                    .collect::<Vec<_>>();
+                let next_continuation_token = page
+                    .is_truncated()
+                    .then(|| page.next_continuation_token().map(String::from));
+
+                Ok::<_, StorageAdapterError>(Some((
+                    futures::stream::iter(entries),
+                    next_continuation_token,
+                )))
             }
+        })
+        .try_flatten()
+        .boxed()
+    }
 
-            let sdk_body = SdkBody::from(chunk.unwrap());
-            let body = ByteStream::new(sdk_body);
-            let upload_part_res = self
-                .client
-                .upload_part()
-                .bucket(&self.bucket)
-                .key(path.to_str().unwrap())
-                .upload_id(upload_id)
-                .body(body)
-                .part_number(1)
-                .send()
-                .await
-                .unwrap();
+    /// Removes the metadata sidecar first, so a failure never leaves
+    /// metadata without its artifact.
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        self.delete_object(&metadata_path(&path)).await?;
+        self.delete_object(&path).await
+    }
 
-            upload_parts.push(
-                CompletedPart::builder()
-                    .e_tag(upload_part_res.e_tag.unwrap_or_default())
-                    .part_number(1)
-                    .build(),
-            );
-        }
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        self.delete_object(&metadata_path(&path)).await
+    }
 
-        let completed_multipart_upload: CompletedMultipartUpload =
-            CompletedMultipartUpload::builder()
-                .set_parts(Some(upload_parts))
-                .build();
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        let artifact = futures::stream::iter([Ok::<_, std::io::Error>(artifact)]);
 
-        let _complete_multipart_upload_res = self
-            .client
-            .complete_multipart_upload()
-            .bucket(&self.bucket)
-            .key(path.to_str().unwrap())
-            .multipart_upload(completed_multipart_upload)
-            .upload_id(upload_id)
-            .send()
-            .await
-            .unwrap();
+        self.upload_stream(&path, artifact).await
+    }
 
-        Ok(())
+    async fn upload_<'a>(&self, path: PathBuf, artifact: Body) -> Result<(), StorageAdapterError> {
+        self.upload_stream(&path, artifact).await
     }
 }
 
+/// Sorts S3 failures into the [`StorageAdapterError`] variants, from the
+/// failure kind, then the S3 error code and finally the HTTP status.
+fn classify<E>(err: SdkError<E, Response<SdkBody>>) -> StorageAdapterError
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    let (status, code) = match &err {
+        SdkError::ConstructionFailure(_) => return StorageAdapterError::InvalidKey(err.into()),
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            return StorageAdapterError::Unavailable(err.into())
+        }
+        SdkError::ServiceError(context) => (
+            context.raw().status().as_u16(),
+            context.err().code().map(String::from),
+        ),
+        _ => return StorageAdapterError::Other(err.into()),
+    };
+
+    match (code.as_deref(), status) {
+        // A missing bucket is a misconfiguration, not a missing artifact.
+        (Some("NoSuchBucket"), _) => StorageAdapterError::Other(err.into()),
+        (Some("NoSuchKey" | "NotFound"), _) | (_, 404) => StorageAdapterError::NotFound(err.into()),
+        (
+            Some(
+                "AccessDenied"
+                | "AllAccessDisabled"
+                | "InvalidAccessKeyId"
+                | "SignatureDoesNotMatch"
+                | "ExpiredToken"
+                | "InvalidToken",
+            ),
+            _,
+        )
+        | (_, 401 | 403) => StorageAdapterError::PermissionDenied(err.into()),
+        (Some("EntityTooLarge" | "QuotaExceeded" | "TooManyBuckets"), _) => {
+            StorageAdapterError::QuotaExceeded(err.into())
+        }
+        (Some("KeyTooLongError" | "InvalidObjectName"), _) => {
+            StorageAdapterError::InvalidKey(err.into())
+        }
+        (
+            Some(
+                "SlowDown"
+                | "ServiceUnavailable"
+                | "InternalError"
+                | "RequestTimeout"
+                | "RequestTimeTooSkewed",
+            ),
+            _,
+        )
+        | (_, 408 | 429 | 500..=599) => StorageAdapterError::Unavailable(err.into()),
+        _ => StorageAdapterError::Other(err.into()),
+    }
+}
+
+/// Reads `artifact` into `buffer` until it holds more than `part_size`
+/// bytes, so that an artifact of exactly `part_size` bytes is known to fit in
+/// a single part. Returns whether `artifact` is over.
+async fn fill<S, E>(
+    artifact: &mut Fuse<S>,
+    buffer: &mut BytesMut,
+    part_size: usize,
+) -> Result<bool, StorageAdapterError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    while buffer.len() <= part_size {
+        match artifact.next().await {
+            Some(chunk) => {
+                let chunk =
+                    chunk.map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+                buffer.extend_from_slice(&chunk);
+            }
+            None => return Ok(true),
+        }
+    }
+
+    Ok(artifact.is_done())
+}
+
+/// Splits the rest of `artifact`, following what was already read into
+/// `buffer`, into parts of `part_size` bytes; the last one may be smaller.
+fn parts<S, E>(
+    artifact: Fuse<S>,
+    buffer: BytesMut,
+    part_size: usize,
+) -> impl Stream<Item = Result<Bytes, StorageAdapterError>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    futures::stream::try_unfold(
+        (artifact, buffer),
+        move |(mut artifact, mut buffer)| async move {
+            fill(&mut artifact, &mut buffer, part_size).await?;
+
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+
+            let part = buffer.split_to(buffer.len().min(part_size)).freeze();
+
+            Ok(Some((part, (artifact, buffer))))
+        },
+    )
+}
+
 pub struct AwsS3StorageAdapterBuilder {
     bucket: Option<String>,
+    prefix: Option<String>,
+    endpoint: Option<String>,
+    force_path_style: Option<bool>,
+    region: Option<String>,
+    credentials: Option<Credentials>,
+    profile: Option<String>,
+    part_size: Option<usize>,
+    upload_concurrency: Option<usize>,
 }
 
 impl AwsS3StorageAdapterBuilder {
     pub async fn build(&self) -> AwsS3StorageAdapter {
-        let bucket = self.bucket.clone().take().unwrap();
-        let aws_config = aws_config::from_env().load().await;
+        let bucket = self.bucket.clone().unwrap();
+
+        let mut loader = aws_config::from_env();
+        if let Some(profile) = &self.profile {
+            loader = loader
+                .region(DefaultRegionChain::builder().profile_name(profile).build())
+                .credentials_provider(
+                    DefaultCredentialsChain::builder()
+                        .profile_name(profile)
+                        .build()
+                        .await,
+                );
+        }
+        if let Some(region) = &self.region {
+            loader = loader.region(Region::new(region.clone()));
+        }
+        if let Some(credentials) = &self.credentials {
+            loader = loader.credentials_provider(credentials.clone());
+        }
+        let aws_config = loader.load().await;
 
-        let client = Client::new(&aws_config);
-        AwsS3StorageAdapter { client, bucket }
+        let mut config = aws_sdk_s3::config::Builder::from(&aws_config);
+        if let Some(endpoint) = &self.endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+        if let Some(force_path_style) = self.force_path_style {
+            config = config.force_path_style(force_path_style);
+        }
+
+        let client = Client::from_conf(config.build());
+        AwsS3StorageAdapter {
+            client,
+            bucket,
+            prefix: self.prefix.clone(),
+            part_size: self.part_size.unwrap_or(PART_SIZE).max(MIN_PART_SIZE),
+            upload_concurrency: self.upload_concurrency.unwrap_or(UPLOAD_CONCURRENCY).max(1),
+        }
     }
 
     pub fn with_bucket(&mut self, bucket: String) -> &mut Self {
@@ -203,4 +530,133 @@ impl AwsS3StorageAdapterBuilder {
 
         self
     }
+
+    /// Stores the artifacts under `prefix` inside the bucket.
+    pub fn with_prefix(&mut self, prefix: String) -> &mut Self {
+        let prefix = prefix.trim_matches('/');
+        if !prefix.is_empty() {
+            self.prefix.replace(prefix.to_string());
+        }
+
+        self
+    }
+
+    /// Sends the requests to `url` rather than to AWS, to use an
+    /// S3-compatible service such as MinIO, Ceph or R2.
+    pub fn with_endpoint_url(&mut self, url: &str) -> Result<&mut Self, InvalidUri> {
+        url.parse::<Uri>()?;
+        self.endpoint.replace(url.to_string());
+
+        Ok(self)
+    }
+
+    /// Addresses buckets path-style, as `<endpoint>/<bucket>/<key>`, rather
+    /// than virtual-hosted-style, as `<bucket>.<endpoint>/<key>`. Services
+    /// such as MinIO usually need it. Defaults to false.
+    pub fn with_force_path_style(&mut self, force_path_style: bool) -> &mut Self {
+        self.force_path_style.replace(force_path_style);
+
+        self
+    }
+
+    /// Overrides the region found in the environment or the profile.
+    pub fn with_region(&mut self, region: String) -> &mut Self {
+        self.region.replace(region);
+
+        self
+    }
+
+    /// Authenticates with a static access key rather than the credentials
+    /// found in the environment or the profile.
+    pub fn with_access_key(
+        &mut self,
+        access_key_id: String,
+        secret_access_key: String,
+    ) -> &mut Self {
+        self.credentials.replace(Credentials::new(
+            access_key_id,
+            secret_access_key,
+            None,
+            None,
+            "turborepo-server",
+        ));
+
+        self
+    }
+
+    /// Reads the region and the credentials from the named profile of the
+    /// shared AWS config files.
+    pub fn with_profile(&mut self, profile: String) -> &mut Self {
+        self.profile.replace(profile);
+
+        self
+    }
+
+    /// Sets the size, in bytes, of the parts of multipart uploads. Artifacts
+    /// up to this size are uploaded with a single request. Defaults to 8 MiB,
+    /// and can't be less than the 5 MiB S3 requires.
+    pub fn with_part_size(&mut self, part_size: usize) -> &mut Self {
+        self.part_size.replace(part_size);
+
+        self
+    }
+
+    /// Sets how many parts of a multipart upload are uploaded at once.
+    /// Defaults to 4.
+    pub fn with_upload_concurrency(&mut self, upload_concurrency: usize) -> &mut Self {
+        self.upload_concurrency.replace(upload_concurrency);
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PART_SIZE: usize = 8;
+
+    /// Splits `len` bytes, streamed in chunks of 3 bytes, the way
+    /// `upload_stream` does. Returns whether they fit a single `PutObject`,
+    /// and the sizes of the parts.
+    async fn split(len: usize) -> (bool, Vec<usize>) {
+        let artifact = vec![0u8; len];
+        let chunks = artifact
+            .chunks(3)
+            .map(|chunk| Ok::<_, std::io::Error>(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let mut artifact = futures::stream::iter(chunks).fuse();
+
+        let mut buffer = BytesMut::new();
+        let ended = fill(&mut artifact, &mut buffer, PART_SIZE).await.unwrap();
+        let single = ended && buffer.len() <= PART_SIZE;
+
+        let parts = parts(artifact, buffer, PART_SIZE)
+            .map_ok(|part| part.len())
+            .try_collect()
+            .await
+            .unwrap();
+
+        (single, parts)
+    }
+
+    #[tokio::test]
+    async fn uploads_artifacts_of_one_part_at_once() {
+        assert_eq!(split(0).await, (true, vec![]));
+        assert_eq!(split(PART_SIZE - 1).await, (true, vec![PART_SIZE - 1]));
+        assert_eq!(split(PART_SIZE).await, (true, vec![PART_SIZE]));
+    }
+
+    #[tokio::test]
+    async fn splits_larger_artifacts_into_parts() {
+        assert_eq!(split(PART_SIZE + 1).await, (false, vec![PART_SIZE, 1]));
+        assert_eq!(
+            split(2 * PART_SIZE).await,
+            (false, vec![PART_SIZE, PART_SIZE])
+        );
+        assert_eq!(
+            split(2 * PART_SIZE + PART_SIZE / 2).await,
+            (false, vec![PART_SIZE, PART_SIZE, PART_SIZE / 2])
+        );
+    }
 }
@@ -0,0 +1,32 @@
+//! Runs against the S3-compatible service at `TURBOREPO_TEST_S3_ENDPOINT`,
+//! in the `TURBOREPO_TEST_S3_BUCKET` bucket (`turborepo` by default), with the
+//! credentials of the environment. For instance, with a local MinIO container:
+//!
+//! ```sh
+//! docker run -p 9000:9000 minio/minio server /data
+//! export AWS_ACCESS_KEY_ID=minioadmin AWS_SECRET_ACCESS_KEY=minioadmin
+//! aws --endpoint-url http://127.0.0.1:9000 s3 mb s3://turborepo
+//! TURBOREPO_TEST_S3_ENDPOINT=http://127.0.0.1:9000 \
+//!     cargo test -p turborepo-aws-s3-storage-adapter -- --ignored
+//! ```
+
+use turborepo_aws_s3_storage_adapter::AwsS3StorageAdapter;
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_S3_ENDPOINT"]
+async fn conformance() {
+    let endpoint =
+        std::env::var("TURBOREPO_TEST_S3_ENDPOINT").expect("TURBOREPO_TEST_S3_ENDPOINT is not set");
+    let bucket = std::env::var("TURBOREPO_TEST_S3_BUCKET").unwrap_or_else(|_| "turborepo".into());
+
+    let storage = AwsS3StorageAdapter::builder()
+        .with_bucket(bucket)
+        .with_endpoint_url(&endpoint)
+        .unwrap()
+        .with_force_path_style(true)
+        .with_region("us-east-1".into())
+        .build()
+        .await;
+
+    turborepo_storage_adapter_tests::run(&storage).await;
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use hyper::{
+    header::{
+        HeaderName, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LANGUAGE, CONTENT_LENGTH,
+        CONTENT_TYPE, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_UNMODIFIED_SINCE, RANGE,
+    },
+    Body, Request,
+};
+use percent_encoding::percent_decode_str;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Account and key of the Azurite emulator, used by
+/// `UseDevelopmentStorage=true`.
+const DEV_ACCOUNT: &str = "devstoreaccount1";
+const DEV_KEY: &str =
+    "Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==";
+const DEV_ENDPOINT: &str = "http://127.0.0.1:10000/devstoreaccount1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AzureCredentialsError {
+    #[error("connection string has no `{0}`")]
+    MissingSetting(&'static str),
+    #[error("invalid connection string setting `{0}`")]
+    InvalidSetting(String),
+    #[error("account key is not base64: {0}")]
+    InvalidKey(#[from] base64::DecodeError),
+}
+
+#[derive(Clone)]
+pub(crate) enum Credentials {
+    /// Requests are signed with the account key.
+    SharedKey { account: String, key: Vec<u8> },
+    /// A shared access signature is appended to the query of requests.
+    SasToken(String),
+}
+
+impl Credentials {
+    pub fn shared_key(account: String, key: &str) -> Result<Credentials, AzureCredentialsError> {
+        Ok(Credentials::SharedKey {
+            account,
+            key: STANDARD.decode(key)?,
+        })
+    }
+
+    pub fn sas_token(token: &str) -> Credentials {
+        Credentials::SasToken(token.trim_start_matches('?').to_string())
+    }
+
+    /// Signs `req` with the account key. Its headers, `Content-Length`
+    /// included, must all be set. SAS tokens are sent in the URL instead, see
+    /// [`sign_url`](Self::sign_url).
+    pub fn authorize(&self, req: &mut Request<Body>) {
+        let Credentials::SharedKey { account, key } = self else {
+            return;
+        };
+
+        let signature = HmacSha256::new_from_slice(key)
+            .expect("HMAC accepts keys of any size")
+            .chain_update(string_to_sign(account, req).as_bytes())
+            .finalize()
+            .into_bytes();
+
+        req.headers_mut().insert(
+            AUTHORIZATION,
+            format!("SharedKey {}:{}", account, STANDARD.encode(signature))
+                .parse()
+                .expect("base64 and account names are valid header values"),
+        );
+    }
+
+    /// Appends the SAS token, if any, to the query of `url`.
+    pub fn sign_url(&self, url: String) -> String {
+        match self {
+            Credentials::SasToken(token) if url.contains('?') => format!("{}&{}", url, token),
+            Credentials::SasToken(token) => format!("{}?{}", url, token),
+            Credentials::SharedKey { .. } => url,
+        }
+    }
+}
+
+/// What a connection string tells: the endpoint of the blob service and the
+/// credentials.
+pub(crate) struct ConnectionString {
+    pub endpoint: String,
+    pub credentials: Credentials,
+}
+
+impl ConnectionString {
+    /// Parses the `Key=Value;...` connection string of a storage account, as
+    /// shown by the Azure portal.
+    pub fn parse(value: &str) -> Result<ConnectionString, AzureCredentialsError> {
+        let settings = value
+            .split(';')
+            .filter(|setting| !setting.trim().is_empty())
+            .map(|setting| {
+                setting
+                    .split_once('=')
+                    .map(|(key, value)| (key.trim(), value.trim()))
+                    .ok_or_else(|| AzureCredentialsError::InvalidSetting(setting.to_string()))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        if settings.get("UseDevelopmentStorage") == Some(&"true") {
+            return Ok(ConnectionString {
+                endpoint: DEV_ENDPOINT.into(),
+                credentials: Credentials::shared_key(DEV_ACCOUNT.into(), DEV_KEY)?,
+            });
+        }
+
+        let account = settings.get("AccountName").copied();
+
+        let endpoint = match (settings.get("BlobEndpoint"), account) {
+            (Some(endpoint), _) => endpoint.to_string(),
+            (None, Some(account)) => format!(
+                "{}://{}.blob.{}",
+                settings.get("DefaultEndpointsProtocol").unwrap_or(&"https"),
+                account,
+                settings
+                    .get("EndpointSuffix")
+                    .unwrap_or(&"core.windows.net")
+            ),
+            (None, None) => return Err(AzureCredentialsError::MissingSetting("AccountName")),
+        };
+
+        let credentials = match (settings.get("SharedAccessSignature"), account) {
+            (Some(token), _) => Credentials::sas_token(token),
+            (None, Some(account)) => Credentials::shared_key(
+                account.to_string(),
+                settings
+                    .get("AccountKey")
+                    .ok_or(AzureCredentialsError::MissingSetting("AccountKey"))?,
+            )?,
+            (None, None) => return Err(AzureCredentialsError::MissingSetting("AccountName")),
+        };
+
+        Ok(ConnectionString {
+            endpoint,
+            credentials,
+        })
+    }
+}
+
+/// The string signed with the account key, as described in "Authorize with
+/// Shared Key".
+fn string_to_sign(account: &str, req: &Request<Body>) -> String {
+    let header = |name: HeaderName| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    };
+
+    let content_length = match header(CONTENT_LENGTH) {
+        "0" => "",
+        content_length => content_length,
+    };
+
+    let mut headers = req
+        .headers()
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-ms-"))
+        .map(|(name, value)| {
+            format!(
+                "{}:{}\n",
+                name.as_str(),
+                value.to_str().unwrap_or_default().trim()
+            )
+        })
+        .collect::<Vec<_>>();
+    headers.sort();
+
+    let mut params = req
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| {
+            (
+                name.to_lowercase(),
+                percent_decode_str(value).decode_utf8_lossy().into_owned(),
+            )
+        })
+        .collect::<Vec<_>>();
+    params.sort();
+
+    let mut resource = format!("/{}{}", account, req.uri().path());
+    for (name, value) in params {
+        resource.push_str(&format!("\n{}:{}", name, value));
+    }
+
+    [
+        req.method().as_str(),
+        header(CONTENT_ENCODING),
+        header(CONTENT_LANGUAGE),
+        content_length,
+        header(HeaderName::from_static("content-md5")),
+        header(CONTENT_TYPE),
+        // The date is sent as `x-ms-date`.
+        "",
+        header(IF_MODIFIED_SINCE),
+        header(IF_MATCH),
+        header(IF_NONE_MATCH),
+        header(IF_UNMODIFIED_SINCE),
+        header(RANGE),
+    ]
+    .join("\n")
+        + "\n"
+        + &headers.concat()
+        + &resource
+}
@@ -0,0 +1,531 @@
+mod auth;
+
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{Bytes, BytesMut};
+use futures::{StreamExt, TryStreamExt};
+use hyper::{
+    client::HttpConnector,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, LAST_MODIFIED},
+    Body, Client, Method, Request, Response,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use turborepo_storage_adapter::{
+    is_metadata_path, metadata_path, ArtifactStream, ObjectEntries, ObjectEntry, ObjectInfo,
+    StorageAdapter, StorageAdapterError,
+};
+
+pub use crate::auth::AzureCredentialsError;
+use crate::auth::{ConnectionString, Credentials};
+
+/// Version of the Blob service REST API the requests are written against.
+const API_VERSION: &str = "2021-08-06";
+/// Default size of the blocks of staged uploads.
+const BLOCK_SIZE: usize = 8 * 1024 * 1024;
+/// Characters escaped in blob names; `/` is kept as a separator.
+const BLOB_NAME: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+pub struct AzureBlobStorageAdapter {
+    client: Client<HttpsConnector<HttpConnector>>,
+    credentials: Credentials,
+    /// URL of the container, `<endpoint>/<container>`.
+    container_url: String,
+    block_size: usize,
+}
+
+impl AzureBlobStorageAdapter {
+    pub fn builder() -> AzureBlobStorageAdapterBuilder {
+        AzureBlobStorageAdapterBuilder {
+            container: None,
+            endpoint: None,
+            credentials: None,
+            block_size: None,
+        }
+    }
+
+    /// URL of the blob at `path`, followed by the `query` parameters, if any.
+    fn blob_url(&self, path: &Path, query: &str) -> Result<String, StorageAdapterError> {
+        let name = path.to_str().ok_or_else(|| {
+            StorageAdapterError::InvalidKey(format!("{:?} is not UTF-8", path).into())
+        })?;
+
+        let mut url = format!(
+            "{}/{}",
+            self.container_url,
+            utf8_percent_encode(name, BLOB_NAME)
+        );
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(query);
+        }
+
+        Ok(self.credentials.sign_url(url))
+    }
+
+    /// Fetches the page of blobs whose name starts with `prefix` that follows
+    /// `marker`, with `List Blobs`. Returns the marker of the next page, if
+    /// any.
+    async fn list_blobs(
+        &self,
+        prefix: &str,
+        marker: Option<&str>,
+    ) -> Result<(Vec<ObjectEntry>, Option<String>), StorageAdapterError> {
+        let mut url = format!(
+            "{}?restype=container&comp=list&prefix={}",
+            self.container_url,
+            utf8_percent_encode(prefix, NON_ALPHANUMERIC)
+        );
+        if let Some(marker) = marker {
+            url.push_str("&marker=");
+            url.extend(utf8_percent_encode(marker, NON_ALPHANUMERIC));
+        }
+
+        let response = self
+            .send(Method::GET, self.credentials.sign_url(url), &[], None)
+            .await?;
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))?;
+
+        // Azure prefixes the document with a byte order mark.
+        let body = std::str::from_utf8(&body)
+            .map_err(|err| StorageAdapterError::Other(err.into()))?
+            .trim_start_matches('\u{feff}');
+        let document = roxmltree::Document::parse(body)
+            .map_err(|err| StorageAdapterError::Other(err.into()))?;
+        let results = document.root_element();
+
+        let entries = child(results, "Blobs")
+            .into_iter()
+            .flat_map(|blobs| blobs.children().filter(|blob| blob.has_tag_name("Blob")))
+            .filter_map(|blob| {
+                let name = child_text(blob, "Name")?;
+                let properties = child(blob, "Properties")?;
+
+                Some(ObjectEntry {
+                    path: name.into(),
+                    info: ObjectInfo {
+                        size: child_text(properties, "Content-Length")?.parse().ok()?,
+                        last_modified: child_text(properties, "Last-Modified").and_then(
+                            |last_modified| httpdate::parse_http_date(last_modified).ok(),
+                        ),
+                    },
+                })
+            })
+            .filter(|entry| !is_metadata_path(&entry.path))
+            .collect();
+        let next_marker = child_text(results, "NextMarker")
+            .filter(|marker| !marker.is_empty())
+            .map(String::from);
+
+        Ok((entries, next_marker))
+    }
+
+    /// Authenticates and sends a request. Transport failures are reported as
+    /// [`StorageAdapterError::Unavailable`], and unsuccessful responses are
+    /// turned into the matching error.
+    async fn send(
+        &self,
+        method: Method,
+        url: String,
+        headers: &[(&str, &str)],
+        body: Option<Bytes>,
+    ) -> Result<Response<Body>, StorageAdapterError> {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(url)
+            .header("x-ms-date", httpdate::fmt_http_date(SystemTime::now()))
+            .header("x-ms-version", API_VERSION);
+
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+
+        // The length is part of the signed headers, so it is set up front.
+        if let Some(body) = &body {
+            builder = builder.header(CONTENT_LENGTH, body.len());
+        }
+
+        let mut req = builder
+            .body(body.map(Body::from).unwrap_or_else(Body::empty))
+            .map_err(|err| StorageAdapterError::InvalidKey(err.into()))?;
+        self.credentials.authorize(&mut req);
+
+        let response = self
+            .client
+            .request(req)
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response).await);
+        }
+
+        Ok(response)
+    }
+
+    /// Removes the blob at `path`, if any.
+    async fn delete_blob(&self, path: &Path) -> Result<(), StorageAdapterError> {
+        match self
+            .send(Method::DELETE, self.blob_url(path, "")?, &[], None)
+            .await
+        {
+            Err(err) if !err.is_not_found() => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Uploads `artifact` as a block blob, with a single request.
+    async fn put_blob(&self, path: &Path, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        self.send(
+            Method::PUT,
+            self.blob_url(path, "")?,
+            &[("x-ms-blob-type", "BlockBlob")],
+            Some(artifact),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stages the rest of `artifact`, following what was already read into
+    /// `buffer`, as blocks of `block_size` bytes, then commits them as the
+    /// blob. Blocks left uncommitted by a failure are discarded by Azure.
+    async fn put_blocks(
+        &self,
+        path: &Path,
+        mut artifact: Body,
+        mut buffer: BytesMut,
+    ) -> Result<(), StorageAdapterError> {
+        // Uncommitted blocks are shared by the uploads of a blob, so each
+        // upload names its own.
+        let nonce: u64 = rand::random();
+        let mut block_ids = vec![];
+
+        loop {
+            let ended = fill(&mut artifact, &mut buffer, self.block_size).await?;
+
+            let block = buffer.split_to(buffer.len().min(self.block_size)).freeze();
+            if !block.is_empty() {
+                // Block ids must all have the same length.
+                let block_id = STANDARD.encode(format!("{:016x}{:05}", nonce, block_ids.len()));
+                let query = format!(
+                    "comp=block&blockid={}",
+                    utf8_percent_encode(&block_id, NON_ALPHANUMERIC)
+                );
+
+                self.send(Method::PUT, self.blob_url(path, &query)?, &[], Some(block))
+                    .await?;
+                block_ids.push(block_id);
+            }
+
+            if ended && buffer.is_empty() {
+                break;
+            }
+        }
+
+        let block_list = block_ids
+            .iter()
+            .map(|block_id| format!("<Latest>{}</Latest>", block_id))
+            .collect::<String>();
+        let block_list = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><BlockList>{}</BlockList>"#,
+            block_list
+        );
+
+        self.send(
+            Method::PUT,
+            self.blob_url(path, "comp=blocklist")?,
+            &[(CONTENT_TYPE.as_str(), "application/xml")],
+            Some(block_list.into()),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StorageAdapter for AzureBlobStorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let artifact = self.get_(path).await?;
+
+        hyper::body::to_bytes(artifact.body)
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))
+    }
+
+    async fn get_(&self, path: PathBuf) -> Result<ArtifactStream, StorageAdapterError> {
+        let response = self
+            .send(Method::GET, self.blob_url(&path, "")?, &[], None)
+            .await?;
+
+        Ok(ArtifactStream {
+            size: content_length(&response)?,
+            body: response.into_body(),
+        })
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
+        Ok(self.stat(path).await?.is_some())
+    }
+
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        let response = match self
+            .send(Method::HEAD, self.blob_url(&path, "")?, &[], None)
+            .await
+        {
+            Ok(response) => response,
+            Err(err) if err.is_not_found() => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Some(ObjectInfo {
+            size: content_length(&response)?,
+            last_modified: header(&response, LAST_MODIFIED.as_str())
+                .and_then(|last_modified| httpdate::parse_http_date(last_modified).ok()),
+        }))
+    }
+
+    /// Lists the blobs a page at a time, fetching the next page once the
+    /// previous one was consumed.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let prefix = prefix.to_string_lossy().into_owned();
+
+        futures::stream::try_unfold(Some(None), move |marker: Option<Option<String>>| {
+            let prefix = prefix.clone();
+
+            async move {
+                let Some(marker) = marker else {
+                    return Ok(None);
+                };
+
+                let (entries, next_marker) = self.list_blobs(&prefix, marker.as_deref()).await?;
+
+                Ok::<_, StorageAdapterError>(Some((
+                    futures::stream::iter(entries.into_iter().map(Ok)),
+                    next_marker.map(Some),
+                )))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Removes the metadata sidecar first, so a failure never leaves
+    /// metadata without its artifact.
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        self.delete_blob(&metadata_path(&path)).await?;
+        self.delete_blob(&path).await
+    }
+
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        self.delete_blob(&metadata_path(&path)).await
+    }
+
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        self.put_blob(&path, artifact).await
+    }
+
+    /// Uploads `artifact` with a single request when it fits in one block,
+    /// or as staged blocks otherwise, so that it is never buffered whole.
+    async fn upload_<'a>(
+        &self,
+        path: PathBuf,
+        mut artifact: Body,
+    ) -> Result<(), StorageAdapterError> {
+        let mut buffer = BytesMut::new();
+        let ended = fill(&mut artifact, &mut buffer, self.block_size).await?;
+
+        if ended {
+            return self.put_blob(&path, buffer.freeze()).await;
+        }
+
+        self.put_blocks(&path, artifact, buffer).await
+    }
+}
+
+fn header<'a>(response: &'a Response<Body>, name: &str) -> Option<&'a str> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Size of the blob a response is about, which Azure always sends.
+fn content_length(response: &Response<Body>) -> Result<u64, StorageAdapterError> {
+    header(response, CONTENT_LENGTH.as_str())
+        .and_then(|size| size.parse().ok())
+        .ok_or_else(|| StorageAdapterError::Other("Azure sent no valid Content-Length".into()))
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).map(|child| child.text().unwrap_or_default())
+}
+
+/// Turns an unsuccessful response into the matching [`StorageAdapterError`].
+async fn status_error(response: Response<Body>) -> StorageAdapterError {
+    let status = response.status();
+    let code = header(&response, "x-ms-error-code")
+        .unwrap_or_default()
+        .to_string();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    let source = format!(
+        "Azure responded {} {}: {}",
+        status,
+        code,
+        String::from_utf8_lossy(&body)
+    )
+    .into();
+
+    match (code.as_str(), status.as_u16()) {
+        (_, 404) => StorageAdapterError::NotFound(source),
+        (_, 401 | 403) => StorageAdapterError::PermissionDenied(source),
+        ("InvalidResourceName" | "InvalidUri", _) => StorageAdapterError::InvalidKey(source),
+        (_, 413) => StorageAdapterError::QuotaExceeded(source),
+        (_, 408 | 429 | 500..=599) => StorageAdapterError::Unavailable(source),
+        _ => StorageAdapterError::Other(source),
+    }
+}
+
+/// Reads `artifact` into `buffer` until it holds more than `block_size`
+/// bytes. Returns whether `artifact` is over.
+async fn fill(
+    artifact: &mut Body,
+    buffer: &mut BytesMut,
+    block_size: usize,
+) -> Result<bool, StorageAdapterError> {
+    while buffer.len() <= block_size {
+        match artifact.next().await {
+            Some(chunk) => {
+                let chunk =
+                    chunk.map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+                buffer.extend_from_slice(&chunk);
+            }
+            None => return Ok(true),
+        }
+    }
+
+    Ok(false)
+}
+
+pub struct AzureBlobStorageAdapterBuilder {
+    container: Option<String>,
+    endpoint: Option<String>,
+    credentials: Option<Credentials>,
+    block_size: Option<usize>,
+}
+
+impl AzureBlobStorageAdapterBuilder {
+    pub fn build(&mut self) -> Result<AzureBlobStorageAdapter, StorageAdapterError> {
+        let container = self
+            .container
+            .clone()
+            .ok_or_else(|| StorageAdapterError::Other("no container was set".into()))?;
+        let credentials = self
+            .credentials
+            .take()
+            .ok_or_else(|| StorageAdapterError::Other("no credentials were set".into()))?;
+
+        let endpoint = match (self.endpoint.take(), &credentials) {
+            (Some(endpoint), _) => endpoint,
+            (None, Credentials::SharedKey { account, .. }) => {
+                format!("https://{}.blob.core.windows.net", account)
+            }
+            (None, Credentials::SasToken(_)) => {
+                return Err(StorageAdapterError::Other(
+                    "a SAS token requires an endpoint".into(),
+                ))
+            }
+        };
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        Ok(AzureBlobStorageAdapter {
+            client: Client::builder().build(connector),
+            credentials,
+            container_url: format!("{}/{}", endpoint.trim_end_matches('/'), container),
+            block_size: self.block_size.unwrap_or(BLOCK_SIZE).max(1),
+        })
+    }
+
+    /// Sets the container the artifacts are stored in.
+    pub fn with_container(&mut self, container: String) -> &mut Self {
+        self.container.replace(container);
+
+        self
+    }
+
+    /// Sets the URL of the blob service, which defaults to
+    /// `https://<account>.blob.core.windows.net`. It is required with a SAS
+    /// token.
+    pub fn with_endpoint(&mut self, endpoint: String) -> &mut Self {
+        self.endpoint.replace(endpoint);
+
+        self
+    }
+
+    /// Takes the endpoint and the credentials from the connection string of
+    /// the storage account. `UseDevelopmentStorage=true` targets Azurite.
+    pub fn with_connection_string(
+        &mut self,
+        connection_string: &str,
+    ) -> Result<&mut Self, AzureCredentialsError> {
+        let connection_string = ConnectionString::parse(connection_string)?;
+        self.endpoint.replace(connection_string.endpoint);
+        self.credentials.replace(connection_string.credentials);
+
+        Ok(self)
+    }
+
+    /// Signs the requests with `key`, a base64 access key of `account`.
+    pub fn with_shared_key(
+        &mut self,
+        account: String,
+        key: &str,
+    ) -> Result<&mut Self, AzureCredentialsError> {
+        self.credentials
+            .replace(Credentials::shared_key(account, key)?);
+
+        Ok(self)
+    }
+
+    /// Authenticates the requests with a shared access signature.
+    pub fn with_sas_token(&mut self, token: &str) -> &mut Self {
+        self.credentials.replace(Credentials::sas_token(token));
+
+        self
+    }
+
+    /// Sets the size, in bytes, of the blocks of staged uploads. Artifacts up
+    /// to this size are uploaded with a single request. Defaults to 8 MiB.
+    pub fn with_block_size(&mut self, block_size: usize) -> &mut Self {
+        self.block_size.replace(block_size);
+
+        self
+    }
+}
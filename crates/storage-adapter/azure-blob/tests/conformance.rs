@@ -0,0 +1,44 @@
+//! Runs against the `TURBOREPO_TEST_AZURE_CONTAINER` container (`turborepo`
+//! by default) of the account of `TURBOREPO_TEST_AZURE_CONNECTION_STRING`. For
+//! instance, with a local Azurite container:
+//!
+//! ```sh
+//! docker run -p 10000:10000 mcr.microsoft.com/azure-storage/azurite azurite-blob --blobHost 0.0.0.0
+//! az storage container create -n turborepo --connection-string "UseDevelopmentStorage=true"
+//! TURBOREPO_TEST_AZURE_CONNECTION_STRING="UseDevelopmentStorage=true" \
+//!     cargo test -p turborepo-azure-blob-storage-adapter -- --ignored
+//! ```
+
+use turborepo_azure_blob_storage_adapter::{
+    AzureBlobStorageAdapter, AzureBlobStorageAdapterBuilder,
+};
+
+fn builder() -> AzureBlobStorageAdapterBuilder {
+    let connection_string = std::env::var("TURBOREPO_TEST_AZURE_CONNECTION_STRING")
+        .expect("TURBOREPO_TEST_AZURE_CONNECTION_STRING is not set");
+    let container =
+        std::env::var("TURBOREPO_TEST_AZURE_CONTAINER").unwrap_or_else(|_| "turborepo".into());
+
+    let mut builder = AzureBlobStorageAdapter::builder();
+    builder
+        .with_container(container)
+        .with_connection_string(&connection_string)
+        .unwrap();
+
+    builder
+}
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_AZURE_CONNECTION_STRING"]
+async fn conformance() {
+    turborepo_storage_adapter_tests::run(&builder().build().unwrap()).await;
+}
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_AZURE_CONNECTION_STRING"]
+async fn concurrent_staged_uploads() {
+    // Small enough for the uploads of the check to be staged as blocks.
+    let storage = builder().with_block_size(16 * 1024).build().unwrap();
+
+    turborepo_storage_adapter_tests::concurrent_writers(&storage).await;
+}
@@ -1,14 +1,33 @@
-use std::path::PathBuf;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use hyper::Body;
 use tokio::{
     fs,
     io::{AsyncReadExt, AsyncWriteExt},
 };
-use turborepo_storage_adapter::{StorageAdapter, StorageAdapterError};
+use tokio_util::io::ReaderStream;
+use turborepo_storage_adapter::{
+    is_metadata_path, metadata_path, ArtifactStream, ObjectEntries, ObjectEntry, ObjectInfo,
+    StorageAdapter, StorageAdapterError,
+};
+
+/// Extension of the files uploads are written to before being renamed into
+/// place.
+const TEMP_EXTENSION: &str = "tmp";
+/// Age after which a temp file is considered left over by an interrupted
+/// upload, and removed on startup.
+const STALE_TEMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Distinguishes the temp files of concurrent uploads of the same artifact.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 pub struct FsStorageAdapter {
     bucket: String,
@@ -19,105 +38,286 @@ impl FsStorageAdapter {
         FsStorageAdapterBuilder { bucket: None }
     }
 
-    async fn _exists(&self, path: &PathBuf) -> Result<(), StorageAdapterError> {
-        let mut dir = path.clone();
+    /// Creates the directory `path` is stored in, if it doesn't exist yet.
+    async fn create_dir(&self, path: &Path) -> Result<(), StorageAdapterError> {
+        let mut dir = path.to_path_buf();
         dir.pop();
 
-        if let Err(_) = fs::read_dir(PathBuf::from(&self.bucket).join(&dir)).await {
-            fs::create_dir_all(PathBuf::from(&self.bucket).join(&dir))
-                .await
-                .unwrap();
+        fs::create_dir_all(PathBuf::from(&self.bucket).join(&dir)).await?;
+
+        Ok(())
+    }
+
+    /// Writes `artifact` to a temp file next to `path`, then renames it into
+    /// place once fully written and synced, so that readers never see a
+    /// partial artifact. The directory is synced too, so that the rename
+    /// survives a crash. The temp file is removed if anything fails.
+    async fn write_atomically<S, E>(
+        &self,
+        path: &Path,
+        artifact: S,
+    ) -> Result<(), StorageAdapterError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.create_dir(path).await?;
+
+        let path = PathBuf::from(&self.bucket).join(path);
+        let temp_path = temp_path(&path);
+
+        let result = async {
+            let mut file = fs::File::create(&temp_path).await?;
+            write_stream(&mut file, artifact).await?;
+            file.sync_all().await?;
+
+            fs::rename(&temp_path, &path).await?;
+            sync_dir(&path).await?;
+
+            Ok(())
         }
+        .await;
 
-        match fs::metadata(PathBuf::from(&self.bucket).join(path)).await {
-            Ok(_) => Ok(()),
-            Err(_) => Err(StorageAdapterError::Unknown),
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path).await;
         }
+
+        result
     }
-}
 
-#[async_trait]
-impl StorageAdapter for FsStorageAdapter {
-    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
-        let mut dir = path.clone();
-        dir.pop();
+    /// Removes the temp files left over by uploads interrupted by a crash or
+    /// a restart.
+    async fn sweep_temp_files(&self) -> Result<(), std::io::Error> {
+        let mut dirs = vec![PathBuf::from(&self.bucket)];
 
-        if let Err(_) = fs::read_dir(PathBuf::from(&self.bucket).join(&dir)).await {
-            fs::create_dir_all(PathBuf::from(&self.bucket).join(&dir))
-                .await
-                .unwrap();
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await?;
+                let path = entry.path();
+
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                let stale = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .is_some_and(|age| age >= STALE_TEMP_AGE);
+
+                if is_temp_path(&path) && stale {
+                    fs::remove_file(&path).await?;
+                }
+            }
         }
 
-        self._exists(&path).await?;
+        Ok(())
+    }
+}
+
+/// Path of a hidden, unique temp file in the directory of `path`:
+/// `.<name>.<pid>.<counter>.tmp`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(
+        ".{}.{}.{}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+        TEMP_EXTENSION
+    ));
+
+    path.with_file_name(name)
+}
+
+/// Syncs the directory holding `path`, making the entries renamed into it
+/// durable.
+#[cfg(unix)]
+async fn sync_dir(path: &Path) -> Result<(), std::io::Error> {
+    match path.parent() {
+        Some(dir) => fs::File::open(dir).await?.sync_all().await,
+        None => Ok(()),
+    }
+}
+
+/// Directories can't be opened, hence synced, on Windows.
+#[cfg(not(unix))]
+async fn sync_dir(_path: &Path) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+/// Removes the file at `path`, if any.
+async fn remove_file(path: &Path) -> Result<(), StorageAdapterError> {
+    match fs::remove_file(path).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+fn is_temp_path(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'));
 
-        let mut file = fs::File::open(PathBuf::from(&self.bucket).join(path))
-            .await
-            .unwrap();
+    hidden && path.extension().is_some_and(|ext| ext == TEMP_EXTENSION)
+}
+
+async fn write_stream<S, E>(file: &mut fs::File, mut artifact: S) -> Result<(), StorageAdapterError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    while let Some(chunk) = artifact.next().await {
+        let chunk = chunk.map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+
+        file.write_all(&chunk).await?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl StorageAdapter for FsStorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let mut file = fs::File::open(PathBuf::from(&self.bucket).join(path)).await?;
 
         let mut buf = vec![];
-        file.read_to_end(&mut buf).await.unwrap();
+        file.read_to_end(&mut buf).await?;
 
         Ok(buf.into())
     }
 
+    async fn get_(&self, path: PathBuf) -> Result<ArtifactStream, StorageAdapterError> {
+        let file = fs::File::open(PathBuf::from(&self.bucket).join(path)).await?;
+        let size = file.metadata().await?.len();
+
+        Ok(ArtifactStream {
+            body: Body::wrap_stream(ReaderStream::new(file)),
+            size,
+        })
+    }
+
     async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
-        let mut dir = path.clone();
-        dir.pop();
+        Ok(self.stat(path).await?.is_some())
+    }
 
-        if let Err(_) = fs::read_dir(PathBuf::from(&self.bucket).join(&dir)).await {
-            fs::create_dir_all(PathBuf::from(&self.bucket).join(&dir))
-                .await
-                .unwrap();
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        match fs::metadata(PathBuf::from(&self.bucket).join(path)).await {
+            Ok(metadata) if !metadata.is_file() => Ok(None),
+            Ok(metadata) => Ok(Some(ObjectInfo {
+                size: metadata.len(),
+                last_modified: metadata.modified().ok(),
+            })),
+            Err(err) => match StorageAdapterError::from(err) {
+                err if err.is_not_found() => Ok(None),
+                err => Err(err),
+            },
         }
-
-        Ok(matches!(
-            fs::metadata(PathBuf::from(&self.bucket).join(path)).await,
-            Ok(_)
-        ))
     }
 
-    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
-        let mut dir = path.clone();
-        dir.pop();
+    /// Walks the directories of the bucket lazily, skipping those that
+    /// can't hold paths starting with `prefix`, metadata sidecars and the
+    /// temp files of ongoing uploads.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let bucket = PathBuf::from(&self.bucket);
+        let prefix = prefix.to_string_lossy().into_owned();
+        let root = match prefix.rfind('/') {
+            Some(end) => bucket.join(&prefix[..end]),
+            None => bucket.clone(),
+        };
 
-        if let Err(_) = fs::read_dir(PathBuf::from(&self.bucket).join(&dir)).await {
-            fs::create_dir_all(PathBuf::from(&self.bucket).join(&dir))
-                .await
-                .unwrap();
-        }
+        futures::stream::try_unfold(
+            (vec![root], None::<fs::ReadDir>),
+            move |(mut dirs, mut entries)| {
+                let bucket = bucket.clone();
+                let prefix = prefix.clone();
 
-        let mut file = fs::File::create(PathBuf::from(&self.bucket).join(path))
-            .await
-            .unwrap();
+                async move {
+                    loop {
+                        let Some(current) = entries.as_mut() else {
+                            let Some(dir) = dirs.pop() else {
+                                return Ok(None);
+                            };
 
-        file.write_all(&artifact.to_vec()).await.unwrap();
+                            entries = match fs::read_dir(&dir).await {
+                                Ok(entries) => Some(entries),
+                                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                                Err(err) => return Err(err.into()),
+                            };
+                            continue;
+                        };
 
-        Ok(())
+                        let Some(entry) = current.next_entry().await? else {
+                            entries = None;
+                            continue;
+                        };
+
+                        let path = entry.path();
+                        let relative = path.strip_prefix(&bucket).unwrap_or(&path).to_path_buf();
+                        let name = relative.to_string_lossy();
+                        let metadata = entry.metadata().await?;
+
+                        if metadata.is_dir() {
+                            if name.starts_with(&prefix)
+                                || prefix.starts_with(&format!("{}/", name))
+                            {
+                                dirs.push(path);
+                            }
+                            continue;
+                        }
+
+                        if !name.starts_with(&prefix)
+                            || is_temp_path(&path)
+                            || is_metadata_path(&path)
+                        {
+                            continue;
+                        }
+
+                        let entry = ObjectEntry {
+                            path: relative,
+                            info: ObjectInfo {
+                                size: metadata.len(),
+                                last_modified: metadata.modified().ok(),
+                            },
+                        };
+
+                        return Ok(Some((entry, (dirs, entries))));
+                    }
+                }
+            },
+        )
+        .boxed()
     }
 
-    async fn upload_<'a>(
-        &self,
-        path: PathBuf,
-        mut artifact: Body,
-    ) -> Result<(), StorageAdapterError> {
-        let mut dir = path.clone();
-        dir.pop();
+    /// Removes the metadata sidecar first, so a failure never leaves
+    /// metadata without its artifact.
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        let path = PathBuf::from(&self.bucket).join(path);
 
-        if let Err(_) = fs::read_dir(PathBuf::from(&self.bucket).join(&dir)).await {
-            fs::create_dir_all(PathBuf::from(&self.bucket).join(&dir))
-                .await
-                .unwrap();
-        }
+        remove_file(&metadata_path(&path)).await?;
+        remove_file(&path).await
+    }
 
-        let mut file = fs::File::create(PathBuf::from(&self.bucket).join(path))
-            .await
-            .unwrap();
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        remove_file(&metadata_path(&PathBuf::from(&self.bucket).join(path))).await
+    }
 
-        while let Some(chunk) = artifact.next().await {
-            file.write(&chunk.unwrap().to_vec()).await.unwrap();
-        }
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        let artifact = futures::stream::iter([Ok::<_, std::io::Error>(artifact)]);
 
-        Ok(())
+        self.write_atomically(&path, artifact).await
+    }
+
+    async fn upload_<'a>(&self, path: PathBuf, artifact: Body) -> Result<(), StorageAdapterError> {
+        self.write_atomically(&path, artifact).await
     }
 }
 
@@ -126,9 +326,16 @@ pub struct FsStorageAdapterBuilder {
 }
 
 impl FsStorageAdapterBuilder {
+    /// Builds the adapter, removing the stale temp files of the bucket.
     pub async fn build(&self) -> FsStorageAdapter {
         let bucket = self.bucket.clone().unwrap();
-        FsStorageAdapter { bucket }
+        let adapter = FsStorageAdapter { bucket };
+
+        if let Err(err) = adapter.sweep_temp_files().await {
+            eprintln!("can't remove stale temp files: {}", err);
+        }
+
+        adapter
     }
 
     pub fn with_bucket(&mut self, bucket: String) -> &mut Self {
@@ -0,0 +1,69 @@
+use std::{
+    fs::{self, File},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use hyper::Body;
+use turborepo_fs_storage_adapter::FsStorageAdapter;
+use turborepo_storage_adapter::StorageAdapter;
+
+async fn storage(bucket: &Path) -> FsStorageAdapter {
+    FsStorageAdapter::builder()
+        .with_bucket(bucket.to_string_lossy().into_owned())
+        .build()
+        .await
+}
+
+/// Names of the files of `dir`, sorted.
+fn files(dir: &Path) -> Vec<String> {
+    let mut files = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    files
+}
+
+#[tokio::test]
+async fn failed_uploads_leave_the_previous_artifact() {
+    let bucket = tempfile::tempdir().unwrap();
+    let storage = storage(bucket.path()).await;
+
+    storage
+        .upload("team/0123abcd".into(), Bytes::from("previous"))
+        .await
+        .unwrap();
+
+    let body = Body::wrap_stream(futures::stream::iter([
+        Ok(Bytes::from("partial")),
+        Err(std::io::Error::other("connection reset")),
+    ]));
+    assert!(storage.upload_("team/0123abcd".into(), body).await.is_err());
+
+    assert_eq!(
+        storage.get("team/0123abcd".into()).await.unwrap(),
+        "previous"
+    );
+    assert_eq!(files(&bucket.path().join("team")), ["0123abcd"]);
+}
+
+#[tokio::test]
+async fn removes_stale_temp_files_on_startup() {
+    let bucket = tempfile::tempdir().unwrap();
+    let team = bucket.path().join("team");
+    fs::create_dir(&team).unwrap();
+
+    let stale = File::create(team.join(".0123abcd.1.0.tmp")).unwrap();
+    stale
+        .set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60))
+        .unwrap();
+    File::create(team.join(".4567ef.1.1.tmp")).unwrap();
+    File::create(team.join("89ab")).unwrap();
+
+    storage(bucket.path()).await;
+
+    assert_eq!(files(&team), [".4567ef.1.1.tmp", "89ab"]);
+}
@@ -0,0 +1,31 @@
+use bytes::Bytes;
+use turborepo_fs_storage_adapter::FsStorageAdapter;
+use turborepo_storage_adapter::StorageAdapter;
+
+#[tokio::test]
+async fn conformance() {
+    let bucket = tempfile::tempdir().unwrap();
+    let storage = FsStorageAdapter::builder()
+        .with_bucket(bucket.path().to_string_lossy().into_owned())
+        .build()
+        .await;
+
+    turborepo_storage_adapter_tests::run(&storage).await;
+}
+
+#[tokio::test]
+async fn directories_are_not_artifacts() {
+    let bucket = tempfile::tempdir().unwrap();
+    let storage = FsStorageAdapter::builder()
+        .with_bucket(bucket.path().to_string_lossy().into_owned())
+        .build()
+        .await;
+
+    storage
+        .upload("team/0123abcd".into(), Bytes::from("artifact"))
+        .await
+        .unwrap();
+
+    assert!(storage.stat("team".into()).await.unwrap().is_none());
+    assert!(!storage.exists("team".into()).await.unwrap());
+}
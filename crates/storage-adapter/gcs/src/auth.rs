@@ -0,0 +1,203 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{
+    client::HttpConnector, header::CONTENT_TYPE, Body, Client, Method, Request, StatusCode,
+};
+use hyper_rustls::HttpsConnector;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use turborepo_storage_adapter::StorageAdapterError;
+
+/// OAuth scope granting read-write access to the objects of a bucket.
+const SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+/// Token endpoint of the metadata server, serving the credentials of the
+/// workload identity.
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+/// Access tokens are refreshed this long before they expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum GcsCredentialsError {
+    #[error("can't read service account file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("can't parse service account file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("invalid service account private key: {0}")]
+    InvalidKey(#[from] jsonwebtoken::errors::Error),
+}
+
+/// The fields of a service account JSON key file used to get tokens.
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+pub(crate) enum Credentials {
+    /// Tokens are obtained with a JWT signed by a service account key.
+    ServiceAccount {
+        client_email: String,
+        key: EncodingKey,
+        token_uri: String,
+    },
+    /// Tokens are obtained from the metadata server, as with workload
+    /// identity on GKE or the default service account of a VM.
+    MetadataServer,
+    /// Requests are sent without token, for emulators such as
+    /// fake-gcs-server.
+    Anonymous,
+}
+
+impl Credentials {
+    pub fn from_service_account_file(path: &Path) -> Result<Credentials, GcsCredentialsError> {
+        let key: ServiceAccountKey = serde_json::from_slice(&std::fs::read(path)?)?;
+
+        Ok(Credentials::ServiceAccount {
+            key: EncodingKey::from_rsa_pem(key.private_key.as_bytes())?,
+            client_email: key.client_email,
+            token_uri: key.token_uri,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+struct Token {
+    value: String,
+    expires_at: Instant,
+}
+
+/// Hands out access tokens, fetching a new one when the cached one is about
+/// to expire.
+pub(crate) struct Authenticator {
+    credentials: Credentials,
+    token: Mutex<Option<Token>>,
+}
+
+impl Authenticator {
+    pub fn new(credentials: Credentials) -> Authenticator {
+        Authenticator {
+            credentials,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Returns the bearer token to send, or `None` for anonymous access.
+    pub async fn token(
+        &self,
+        client: &Client<HttpsConnector<HttpConnector>>,
+    ) -> Result<Option<String>, StorageAdapterError> {
+        if let Credentials::Anonymous = self.credentials {
+            return Ok(None);
+        }
+
+        let mut token = self.token.lock().await;
+
+        if let Some(token) = token.as_ref() {
+            if token.expires_at > Instant::now() + EXPIRY_MARGIN {
+                return Ok(Some(token.value.clone()));
+            }
+        }
+
+        let response = self.fetch(client).await?;
+        let value = response.access_token.clone();
+        token.replace(Token {
+            value: response.access_token,
+            expires_at: Instant::now() + Duration::from_secs(response.expires_in),
+        });
+
+        Ok(Some(value))
+    }
+
+    async fn fetch(
+        &self,
+        client: &Client<HttpsConnector<HttpConnector>>,
+    ) -> Result<TokenResponse, StorageAdapterError> {
+        let req = match &self.credentials {
+            Credentials::ServiceAccount {
+                client_email,
+                key,
+                token_uri,
+            } => {
+                let iat = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let assertion = jsonwebtoken::encode(
+                    &Header::new(Algorithm::RS256),
+                    &Claims {
+                        iss: client_email,
+                        scope: SCOPE,
+                        aud: token_uri,
+                        iat,
+                        exp: iat + 3600,
+                    },
+                    key,
+                )
+                .map_err(|err| StorageAdapterError::PermissionDenied(err.into()))?;
+
+                let body = url::form_urlencoded::Serializer::new(String::new())
+                    .append_pair("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer")
+                    .append_pair("assertion", &assertion)
+                    .finish();
+
+                Request::builder()
+                    .method(Method::POST)
+                    .uri(token_uri)
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(body))
+            }
+            Credentials::MetadataServer => Request::builder()
+                .method(Method::GET)
+                .uri(METADATA_TOKEN_URL)
+                .header("Metadata-Flavor", "Google")
+                .body(Body::empty()),
+            Credentials::Anonymous => unreachable!("anonymous access has no token"),
+        }
+        .map_err(|err| StorageAdapterError::Other(err.into()))?;
+
+        let response = client
+            .request(req)
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))?;
+
+        if status != StatusCode::OK {
+            let message = format!(
+                "can't get an access token, {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            );
+
+            return Err(if status.is_server_error() {
+                StorageAdapterError::Unavailable(message.into())
+            } else {
+                StorageAdapterError::PermissionDenied(message.into())
+            });
+        }
+
+        serde_json::from_slice(&body).map_err(|err| StorageAdapterError::Other(err.into()))
+    }
+}
@@ -0,0 +1,605 @@
+mod auth;
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::{StreamExt, TryStreamExt};
+use hyper::{
+    client::HttpConnector,
+    header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, LOCATION, RANGE},
+    Body, Client, Method, Request, Response, StatusCode,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use turborepo_storage_adapter::{
+    is_metadata_path, metadata_path, ArtifactStream, ObjectEntries, ObjectEntry, ObjectInfo,
+    StorageAdapter, StorageAdapterError,
+};
+
+pub use crate::auth::GcsCredentialsError;
+use crate::auth::{Authenticator, Credentials};
+
+/// Endpoint of the Cloud Storage JSON API.
+const ENDPOINT: &str = "https://storage.googleapis.com";
+/// Resumable uploads are sent in chunks that are multiples of 256 KiB.
+const CHUNK_GRANULARITY: usize = 256 * 1024;
+/// Default size of the chunks of resumable uploads.
+const CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// The fields of an object resource used by [`GcsStorageAdapter::stat`].
+#[derive(Deserialize)]
+struct Object {
+    /// Size in bytes, as a decimal string.
+    size: String,
+    /// RFC 3339 timestamp of the last update.
+    updated: Option<String>,
+}
+
+impl Object {
+    fn info(self) -> ObjectInfo {
+        ObjectInfo {
+            size: self.size.parse().unwrap_or_default(),
+            last_modified: self
+                .updated
+                .and_then(|updated| OffsetDateTime::parse(&updated, &Rfc3339).ok())
+                .map(Into::into),
+        }
+    }
+}
+
+/// A page of objects, as listed by [`GcsStorageAdapter::list`].
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Objects {
+    #[serde(default)]
+    items: Vec<ListedObject>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListedObject {
+    name: String,
+    #[serde(flatten)]
+    object: Object,
+}
+
+pub struct GcsStorageAdapter {
+    client: Client<HttpsConnector<HttpConnector>>,
+    auth: Authenticator,
+    endpoint: String,
+    bucket: String,
+    chunk_size: usize,
+}
+
+impl GcsStorageAdapter {
+    pub fn builder() -> GcsStorageAdapterBuilder {
+        GcsStorageAdapterBuilder {
+            bucket: None,
+            endpoint: None,
+            credentials: None,
+            chunk_size: None,
+        }
+    }
+
+    fn object_name(path: &Path) -> Result<String, StorageAdapterError> {
+        let name = path.to_str().ok_or_else(|| {
+            StorageAdapterError::InvalidKey(format!("{:?} is not UTF-8", path).into())
+        })?;
+
+        Ok(utf8_percent_encode(name, NON_ALPHANUMERIC).to_string())
+    }
+
+    /// URL of the object at `path`, as a JSON API resource.
+    fn object_url(&self, path: &Path) -> Result<String, StorageAdapterError> {
+        Ok(format!(
+            "{}/storage/v1/b/{}/o/{}",
+            self.endpoint,
+            self.bucket,
+            Self::object_name(path)?
+        ))
+    }
+
+    /// URL uploading the object at `path` with the given `uploadType`.
+    fn upload_url(&self, path: &Path, upload_type: &str) -> Result<String, StorageAdapterError> {
+        Ok(format!(
+            "{}/upload/storage/v1/b/{}/o?uploadType={}&name={}",
+            self.endpoint,
+            self.bucket,
+            upload_type,
+            Self::object_name(path)?
+        ))
+    }
+
+    /// Sends `req` with an access token. Transport failures are reported as
+    /// [`StorageAdapterError::Unavailable`], but the response is returned
+    /// whatever its status.
+    async fn send(&self, mut req: Request<Body>) -> Result<Response<Body>, StorageAdapterError> {
+        if let Some(token) = self.auth.token(&self.client).await? {
+            req.headers_mut().insert(
+                AUTHORIZATION,
+                format!("Bearer {}", token).parse().map_err(
+                    |err: hyper::header::InvalidHeaderValue| {
+                        StorageAdapterError::PermissionDenied(err.into())
+                    },
+                )?,
+            );
+        }
+
+        self.client
+            .request(req)
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))
+    }
+
+    /// Sends `req`, failing unless the response is successful.
+    async fn send_ok(&self, req: Request<Body>) -> Result<Response<Body>, StorageAdapterError> {
+        let response = self.send(req).await?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response).await);
+        }
+
+        Ok(response)
+    }
+
+    /// Fetches the object resource at `url`, or `None` if there is none.
+    async fn object(&self, url: String) -> Result<Option<Object>, StorageAdapterError> {
+        let response = match self
+            .send_ok(request(Method::GET, url, Body::empty())?)
+            .await
+        {
+            Ok(response) => response,
+            Err(err) if err.is_not_found() => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))?;
+
+        serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(|err| StorageAdapterError::Other(err.into()))
+    }
+
+    /// Removes the object at `path`, if any.
+    async fn delete_object(&self, path: &Path) -> Result<(), StorageAdapterError> {
+        match self
+            .send_ok(request(
+                Method::DELETE,
+                self.object_url(path)?,
+                Body::empty(),
+            )?)
+            .await
+        {
+            Err(err) if !err.is_not_found() => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Uploads `artifact` with a single request.
+    async fn upload_media(&self, path: &Path, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        self.send_ok(request(
+            Method::POST,
+            self.upload_url(path, "media")?,
+            Body::from(artifact),
+        )?)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Sends the rest of `artifact`, following what was already read into
+    /// `buffer`, as a resumable upload of `chunk_size` chunks.
+    async fn upload_resumable(
+        &self,
+        path: &Path,
+        mut artifact: Body,
+        mut buffer: BytesMut,
+    ) -> Result<(), StorageAdapterError> {
+        let response = self
+            .send_ok(request(
+                Method::POST,
+                self.upload_url(path, "resumable")?,
+                Body::empty(),
+            )?)
+            .await?;
+
+        let session = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .map(String::from)
+            .ok_or_else(|| StorageAdapterError::Other("GCS returned no upload session".into()))?;
+
+        let result = async {
+            let mut offset = 0;
+
+            loop {
+                let ended = fill(&mut artifact, &mut buffer, self.chunk_size).await?;
+
+                if ended {
+                    let chunk = buffer.split().freeze();
+                    return self.upload_chunk(&session, chunk, offset, true).await;
+                }
+
+                let chunk = buffer.split_to(self.chunk_size).freeze();
+                let len = chunk.len() as u64;
+                self.upload_chunk(&session, chunk, offset, false).await?;
+                offset += len;
+            }
+        }
+        .await;
+
+        if result.is_err() {
+            // Cancels the upload session.
+            if let Ok(req) = request(Method::DELETE, session, Body::empty()) {
+                let _ = self.send(req).await;
+            }
+        }
+
+        result
+    }
+
+    /// Sends the chunk of a resumable upload starting at `offset`. The total
+    /// size is only known, and sent, with the `last` chunk.
+    async fn upload_chunk(
+        &self,
+        session: &str,
+        chunk: Bytes,
+        offset: u64,
+        last: bool,
+    ) -> Result<(), StorageAdapterError> {
+        let len = chunk.len() as u64;
+        let end = offset + len;
+
+        let range = match (len, last) {
+            (0, _) => format!("bytes */{}", end),
+            (_, true) => format!("bytes {}-{}/{}", offset, end - 1, end),
+            (_, false) => format!("bytes {}-{}/*", offset, end - 1),
+        };
+
+        let mut req = request(Method::PUT, session.to_string(), Body::from(chunk))?;
+        req.headers_mut().insert(
+            CONTENT_RANGE,
+            range
+                .parse()
+                .map_err(|err: hyper::header::InvalidHeaderValue| {
+                    StorageAdapterError::Other(err.into())
+                })?,
+        );
+
+        let response = self.send(req).await?;
+
+        if last {
+            if !response.status().is_success() {
+                return Err(status_error(response).await);
+            }
+
+            return Ok(());
+        }
+
+        if response.status() != StatusCode::PERMANENT_REDIRECT {
+            return Err(status_error(response).await);
+        }
+
+        // GCS reports the bytes it persisted, `bytes=0-<last byte>`.
+        let persisted = response
+            .headers()
+            .get(RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.rsplit('-').next())
+            .and_then(|last| last.parse::<u64>().ok())
+            .map(|last| last + 1);
+
+        if persisted != Some(end) {
+            return Err(StorageAdapterError::Unavailable(
+                format!("GCS persisted {:?} of {} bytes", persisted, end).into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StorageAdapter for GcsStorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let artifact = self.get_(path).await?;
+
+        hyper::body::to_bytes(artifact.body)
+            .await
+            .map_err(|err| StorageAdapterError::Unavailable(err.into()))
+    }
+
+    async fn get_(&self, path: PathBuf) -> Result<ArtifactStream, StorageAdapterError> {
+        let url = format!("{}?alt=media", self.object_url(&path)?);
+        let response = self
+            .send_ok(request(Method::GET, url, Body::empty())?)
+            .await?;
+
+        let size =
+            match header(&response, CONTENT_LENGTH.as_str()).and_then(|value| value.parse().ok()) {
+                Some(size) => size,
+                // Transcoded objects are sent without their length, which is
+                // read from the resource of the generation being downloaded.
+                None => {
+                    let mut url = self.object_url(&path)?;
+                    if let Some(generation) = header(&response, "x-goog-generation") {
+                        url.push_str("?generation=");
+                        url.push_str(generation);
+                    }
+
+                    self.object(url)
+                        .await?
+                        .ok_or_else(|| {
+                            StorageAdapterError::NotFound(
+                                format!("{:?} was removed while read", path).into(),
+                            )
+                        })?
+                        .info()
+                        .size
+                }
+            };
+
+        Ok(ArtifactStream {
+            body: response.into_body(),
+            size,
+        })
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
+        Ok(self.stat(path).await?.is_some())
+    }
+
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        Ok(self
+            .object(self.object_url(&path)?)
+            .await?
+            .map(Object::info))
+    }
+
+    /// Lists the objects a page at a time, fetching the next page once the
+    /// previous one was consumed.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let prefix = match Self::object_name(&prefix) {
+            Ok(prefix) => prefix,
+            Err(err) => return futures::stream::once(async { Err(err) }).boxed(),
+        };
+
+        futures::stream::try_unfold(Some(None), move |page_token: Option<Option<String>>| {
+            let prefix = prefix.clone();
+
+            async move {
+                let Some(page_token) = page_token else {
+                    return Ok(None);
+                };
+
+                let mut url = format!(
+                    "{}/storage/v1/b/{}/o?prefix={}&fields=items(name,size,updated),nextPageToken",
+                    self.endpoint, self.bucket, prefix
+                );
+                if let Some(page_token) = page_token {
+                    url.push_str("&pageToken=");
+                    url.extend(utf8_percent_encode(&page_token, NON_ALPHANUMERIC));
+                }
+
+                let response = self
+                    .send_ok(request(Method::GET, url, Body::empty())?)
+                    .await?;
+                let body = hyper::body::to_bytes(response.into_body())
+                    .await
+                    .map_err(|err| StorageAdapterError::Unavailable(err.into()))?;
+                let objects: Objects = serde_json::from_slice(&body)
+                    .map_err(|err| StorageAdapterError::Other(err.into()))?;
+
+                let entries = objects
+                    .items
+                    .into_iter()
+                    .filter(|object| !is_metadata_path(Path::new(&object.name)))
+                    .map(|object| {
+                        Ok(ObjectEntry {
+                            path: object.name.into(),
+                            info: object.object.info(),
+                        })
+                    });
+
+                Ok::<_, StorageAdapterError>(Some((
+                    futures::stream::iter(entries),
+                    objects.next_page_token.map(Some),
+                )))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Removes the metadata sidecar first, so a failure never leaves
+    /// metadata without its artifact.
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        self.delete_object(&metadata_path(&path)).await?;
+        self.delete_object(&path).await
+    }
+
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        self.delete_object(&metadata_path(&path)).await
+    }
+
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        self.upload_media(&path, artifact).await
+    }
+
+    /// Uploads `artifact` with a single request when it fits in one chunk,
+    /// or as a resumable upload otherwise.
+    async fn upload_<'a>(
+        &self,
+        path: PathBuf,
+        mut artifact: Body,
+    ) -> Result<(), StorageAdapterError> {
+        let mut buffer = BytesMut::new();
+        let ended = fill(&mut artifact, &mut buffer, self.chunk_size).await?;
+
+        if ended && buffer.len() <= self.chunk_size {
+            return self.upload_media(&path, buffer.freeze()).await;
+        }
+
+        self.upload_resumable(&path, artifact, buffer).await
+    }
+}
+
+fn request(method: Method, url: String, body: Body) -> Result<Request<Body>, StorageAdapterError> {
+    Request::builder()
+        .method(method)
+        .uri(url)
+        .body(body)
+        .map_err(|err| StorageAdapterError::InvalidKey(err.into()))
+}
+
+fn header<'a>(response: &'a Response<Body>, name: &str) -> Option<&'a str> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Turns an unsuccessful response into the matching [`StorageAdapterError`].
+async fn status_error(response: Response<Body>) -> StorageAdapterError {
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    let source = format!(
+        "GCS responded {}: {}",
+        status,
+        String::from_utf8_lossy(&body)
+    )
+    .into();
+
+    match status.as_u16() {
+        404 => StorageAdapterError::NotFound(source),
+        401 | 403 => StorageAdapterError::PermissionDenied(source),
+        413 => StorageAdapterError::QuotaExceeded(source),
+        408 | 429 | 500..=599 => StorageAdapterError::Unavailable(source),
+        _ => StorageAdapterError::Other(source),
+    }
+}
+
+/// Reads `artifact` into `buffer` until it holds more than `chunk_size`
+/// bytes. Returns whether `artifact` is over.
+async fn fill(
+    artifact: &mut Body,
+    buffer: &mut BytesMut,
+    chunk_size: usize,
+) -> Result<bool, StorageAdapterError> {
+    while buffer.len() <= chunk_size {
+        match artifact.next().await {
+            Some(chunk) => {
+                let chunk =
+                    chunk.map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+                buffer.extend_from_slice(&chunk);
+            }
+            None => return Ok(true),
+        }
+    }
+
+    Ok(false)
+}
+
+pub struct GcsStorageAdapterBuilder {
+    bucket: Option<String>,
+    endpoint: Option<String>,
+    credentials: Option<Credentials>,
+    chunk_size: Option<usize>,
+}
+
+impl GcsStorageAdapterBuilder {
+    /// Builds the adapter. Without explicit credentials, the service account
+    /// file named by `GOOGLE_APPLICATION_CREDENTIALS` is used if set, and the
+    /// metadata server otherwise.
+    pub fn build(&mut self) -> Result<GcsStorageAdapter, GcsCredentialsError> {
+        let bucket = self.bucket.clone().unwrap();
+
+        let credentials = match self.credentials.take() {
+            Some(credentials) => credentials,
+            None => match std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS") {
+                Some(path) => Credentials::from_service_account_file(Path::new(&path))?,
+                None => Credentials::MetadataServer,
+            },
+        };
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        let chunk_size = self.chunk_size.unwrap_or(CHUNK_SIZE).max(CHUNK_GRANULARITY)
+            / CHUNK_GRANULARITY
+            * CHUNK_GRANULARITY;
+
+        Ok(GcsStorageAdapter {
+            client: Client::builder().build(connector),
+            auth: Authenticator::new(credentials),
+            endpoint: self
+                .endpoint
+                .take()
+                .unwrap_or_else(|| ENDPOINT.into())
+                .trim_end_matches('/')
+                .to_string(),
+            bucket,
+            chunk_size,
+        })
+    }
+
+    pub fn with_bucket(&mut self, bucket: String) -> &mut Self {
+        self.bucket.replace(bucket);
+
+        self
+    }
+
+    /// Sends the requests to `endpoint` rather than to Google, to use an
+    /// emulator such as fake-gcs-server.
+    pub fn with_endpoint(&mut self, endpoint: String) -> &mut Self {
+        self.endpoint.replace(endpoint);
+
+        self
+    }
+
+    /// Authenticates with the service account JSON key file at `path`.
+    pub fn with_service_account_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<&mut Self, GcsCredentialsError> {
+        self.credentials
+            .replace(Credentials::from_service_account_file(path.as_ref())?);
+
+        Ok(self)
+    }
+
+    /// Authenticates with the tokens of the metadata server, as provided by
+    /// workload identity.
+    pub fn with_metadata_server(&mut self) -> &mut Self {
+        self.credentials.replace(Credentials::MetadataServer);
+
+        self
+    }
+
+    /// Sends the requests without credentials, for emulators.
+    pub fn with_anonymous_access(&mut self) -> &mut Self {
+        self.credentials.replace(Credentials::Anonymous);
+
+        self
+    }
+
+    /// Sets the size, in bytes, of the chunks of resumable uploads, rounded
+    /// down to a multiple of 256 KiB. Artifacts up to this size are uploaded
+    /// with a single request. Defaults to 8 MiB.
+    pub fn with_chunk_size(&mut self, chunk_size: usize) -> &mut Self {
+        self.chunk_size.replace(chunk_size);
+
+        self
+    }
+}
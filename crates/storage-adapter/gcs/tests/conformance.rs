@@ -0,0 +1,41 @@
+//! Runs anonymously against the `TURBOREPO_TEST_GCS_BUCKET` bucket
+//! (`turborepo` by default) of the fake-gcs-server at
+//! `TURBOREPO_TEST_GCS_ENDPOINT`. For instance, with a local container:
+//!
+//! ```sh
+//! docker run -p 4443:4443 fsouza/fake-gcs-server -scheme http -public-host 127.0.0.1:4443
+//! curl -X POST http://127.0.0.1:4443/storage/v1/b -d '{"name": "turborepo"}'
+//! TURBOREPO_TEST_GCS_ENDPOINT=http://127.0.0.1:4443 \
+//!     cargo test -p turborepo-gcs-storage-adapter -- --ignored
+//! ```
+
+use turborepo_gcs_storage_adapter::{GcsStorageAdapter, GcsStorageAdapterBuilder};
+
+fn builder() -> GcsStorageAdapterBuilder {
+    let endpoint = std::env::var("TURBOREPO_TEST_GCS_ENDPOINT")
+        .expect("TURBOREPO_TEST_GCS_ENDPOINT is not set");
+    let bucket = std::env::var("TURBOREPO_TEST_GCS_BUCKET").unwrap_or_else(|_| "turborepo".into());
+
+    let mut builder = GcsStorageAdapter::builder();
+    builder
+        .with_bucket(bucket)
+        .with_endpoint(endpoint)
+        .with_anonymous_access();
+
+    builder
+}
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_GCS_ENDPOINT"]
+async fn conformance() {
+    turborepo_storage_adapter_tests::run(&builder().build().unwrap()).await;
+}
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_GCS_ENDPOINT"]
+async fn concurrent_resumable_uploads() {
+    // Small enough for the uploads of the check to be resumable.
+    let storage = builder().with_chunk_size(256 * 1024).build().unwrap();
+
+    turborepo_storage_adapter_tests::concurrent_writers(&storage).await;
+}
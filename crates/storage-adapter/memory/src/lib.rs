@@ -0,0 +1,253 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::StreamExt;
+use hyper::Body;
+use turborepo_storage_adapter::{
+    ArtifactMetadata, ObjectEntries, ObjectEntry, ObjectInfo, StorageAdapter, StorageAdapterError,
+};
+
+struct Entry {
+    data: Bytes,
+    /// Metadata of the artifact, kept with it rather than in a sidecar so
+    /// both are evicted together.
+    metadata: Option<ArtifactMetadata>,
+    last_modified: SystemTime,
+    /// Position of the entry in [`Objects::recency`].
+    last_used: u64,
+}
+
+/// Objects along with the order they were last used in.
+#[derive(Default)]
+struct Objects {
+    entries: HashMap<PathBuf, Entry>,
+    /// Paths of the entries, from the least to the most recently used.
+    recency: BTreeMap<u64, PathBuf>,
+    clock: u64,
+    size: u64,
+}
+
+impl Objects {
+    fn touch(&mut self, path: &Path) -> Option<&Entry> {
+        self.clock += 1;
+        let clock = self.clock;
+
+        let entry = self.entries.get_mut(path)?;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(clock, path.to_path_buf());
+        entry.last_used = clock;
+
+        Some(entry)
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.recency.remove(&entry.last_used);
+            self.size -= entry.data.len() as u64;
+        }
+    }
+
+    fn insert(&mut self, path: PathBuf, data: Bytes) {
+        self.remove(&path);
+
+        self.clock += 1;
+        self.size += data.len() as u64;
+        self.recency.insert(self.clock, path.clone());
+        self.entries.insert(
+            path,
+            Entry {
+                data,
+                metadata: None,
+                last_modified: SystemTime::now(),
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Evicts the least recently used entries, along with their metadata,
+    /// until the objects fit in `max_size` bytes. Metadata isn't counted in
+    /// the size.
+    fn evict(&mut self, max_size: u64) {
+        while self.size > max_size {
+            let Some(path) = self.recency.values().next().cloned() else {
+                break;
+            };
+
+            self.remove(&path);
+        }
+    }
+}
+
+/// Keeps the artifacts in memory, for throwaway CI runners, ephemeral
+/// environments and tests. Everything is lost when the server stops.
+///
+/// When a maximum size is set, the least recently used objects are evicted
+/// to make room for new ones.
+pub struct MemoryStorageAdapter {
+    objects: Mutex<Objects>,
+    max_size: Option<u64>,
+}
+
+impl MemoryStorageAdapter {
+    pub fn builder() -> MemoryStorageAdapterBuilder {
+        MemoryStorageAdapterBuilder { max_size: None }
+    }
+
+    fn not_found(path: &Path) -> StorageAdapterError {
+        StorageAdapterError::NotFound(format!("{:?} is not stored", path).into())
+    }
+}
+
+#[async_trait]
+impl StorageAdapter for MemoryStorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let mut objects = self.objects.lock().unwrap();
+
+        objects
+            .touch(&path)
+            .map(|entry| entry.data.clone())
+            .ok_or_else(|| Self::not_found(&path))
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
+        Ok(self.objects.lock().unwrap().entries.contains_key(&path))
+    }
+
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        Ok(self
+            .objects
+            .lock()
+            .unwrap()
+            .entries
+            .get(&path)
+            .map(|entry| ObjectInfo {
+                size: entry.data.len() as u64,
+                last_modified: Some(entry.last_modified),
+            }))
+    }
+
+    /// Lists a snapshot of the artifacts, taken when called.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let prefix = prefix.to_string_lossy().into_owned();
+        let entries = self
+            .objects
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .filter(|(path, _)| path.to_string_lossy().starts_with(&prefix))
+            .map(|(path, entry)| {
+                Ok(ObjectEntry {
+                    path: path.clone(),
+                    info: ObjectInfo {
+                        size: entry.data.len() as u64,
+                        last_modified: Some(entry.last_modified),
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        futures::stream::iter(entries).boxed()
+    }
+
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        self.objects.lock().unwrap().remove(&path);
+
+        Ok(())
+    }
+
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        if let Some(max_size) = self.max_size {
+            if artifact.len() as u64 > max_size {
+                return Err(StorageAdapterError::QuotaExceeded(
+                    format!(
+                        "{} bytes don't fit in the {} bytes of the storage",
+                        artifact.len(),
+                        max_size
+                    )
+                    .into(),
+                ));
+            }
+        }
+
+        let mut objects = self.objects.lock().unwrap();
+        objects.insert(path, artifact);
+
+        if let Some(max_size) = self.max_size {
+            objects.evict(max_size);
+        }
+
+        Ok(())
+    }
+
+    async fn upload_<'a>(&self, path: PathBuf, artifact: Body) -> Result<(), StorageAdapterError> {
+        let artifact = hyper::body::to_bytes(artifact)
+            .await
+            .map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+
+        self.upload(path, artifact).await
+    }
+
+    async fn get_metadata(
+        &self,
+        path: PathBuf,
+    ) -> Result<Option<ArtifactMetadata>, StorageAdapterError> {
+        Ok(self
+            .objects
+            .lock()
+            .unwrap()
+            .entries
+            .get(&path)
+            .and_then(|entry| entry.metadata.clone()))
+    }
+
+    /// Attaches `metadata` to the artifact at `path`, which must be stored.
+    async fn put_metadata(
+        &self,
+        path: PathBuf,
+        metadata: &ArtifactMetadata,
+    ) -> Result<(), StorageAdapterError> {
+        let mut objects = self.objects.lock().unwrap();
+        let entry = objects
+            .entries
+            .get_mut(&path)
+            .ok_or_else(|| Self::not_found(&path))?;
+        entry.metadata.replace(metadata.clone());
+
+        Ok(())
+    }
+
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        if let Some(entry) = self.objects.lock().unwrap().entries.get_mut(&path) {
+            entry.metadata.take();
+        }
+
+        Ok(())
+    }
+}
+
+pub struct MemoryStorageAdapterBuilder {
+    max_size: Option<u64>,
+}
+
+impl MemoryStorageAdapterBuilder {
+    pub fn build(&self) -> MemoryStorageAdapter {
+        MemoryStorageAdapter {
+            objects: Mutex::new(Objects::default()),
+            max_size: self.max_size,
+        }
+    }
+
+    /// Limits the total size, in bytes, of the stored objects.
+    pub fn with_max_size(&mut self, max_size: u64) -> &mut Self {
+        self.max_size.replace(max_size);
+
+        self
+    }
+}
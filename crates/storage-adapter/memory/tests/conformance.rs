@@ -0,0 +1,8 @@
+use turborepo_memory_storage_adapter::MemoryStorageAdapter;
+
+#[tokio::test]
+async fn conformance() {
+    let storage = MemoryStorageAdapter::builder().build();
+
+    turborepo_storage_adapter_tests::run(&storage).await;
+}
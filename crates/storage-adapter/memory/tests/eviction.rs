@@ -0,0 +1,70 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use turborepo_memory_storage_adapter::MemoryStorageAdapter;
+use turborepo_storage_adapter::{ArtifactMetadata, StorageAdapter};
+
+fn metadata(size: u64) -> ArtifactMetadata {
+    ArtifactMetadata {
+        size,
+        duration: Some(100),
+        tag: Some("tag".to_string()),
+        content_type: None,
+        uploaded_at: SystemTime::now(),
+    }
+}
+
+#[tokio::test]
+async fn evicts_artifacts_with_their_metadata() {
+    let storage = MemoryStorageAdapter::builder().with_max_size(8).build();
+    let (first, second) = (PathBuf::from("team/first"), PathBuf::from("team/second"));
+
+    storage.upload(first.clone(), "1234".into()).await.unwrap();
+    storage
+        .put_metadata(first.clone(), &metadata(4))
+        .await
+        .unwrap();
+    storage.upload(second.clone(), "5678".into()).await.unwrap();
+    storage
+        .put_metadata(second.clone(), &metadata(4))
+        .await
+        .unwrap();
+
+    // Metadata doesn't take room: both artifacts fit, with their tags.
+    assert!(storage.get_metadata(first.clone()).await.unwrap().is_some());
+    assert!(storage
+        .get_metadata(second.clone())
+        .await
+        .unwrap()
+        .is_some());
+
+    storage.get(first.clone()).await.unwrap();
+    storage
+        .upload(PathBuf::from("team/third"), "9".into())
+        .await
+        .unwrap();
+
+    assert!(storage.exists(first.clone()).await.unwrap());
+    assert!(storage.get_metadata(first).await.unwrap().is_some());
+    assert!(!storage.exists(second.clone()).await.unwrap());
+    assert!(storage.get_metadata(second).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn drops_metadata_on_overwrite() {
+    let storage = MemoryStorageAdapter::builder().build();
+    let path = PathBuf::from("team/artifact");
+
+    storage.upload(path.clone(), "1234".into()).await.unwrap();
+    storage
+        .put_metadata(path.clone(), &metadata(4))
+        .await
+        .unwrap();
+    storage.upload(path.clone(), "56".into()).await.unwrap();
+
+    assert!(storage.get_metadata(path.clone()).await.unwrap().is_none());
+    assert!(storage
+        .put_metadata(PathBuf::from("team/missing"), &metadata(0))
+        .await
+        .unwrap_err()
+        .is_not_found());
+}
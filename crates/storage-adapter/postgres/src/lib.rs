@@ -0,0 +1,505 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use hyper::Body;
+use sqlx::{
+    postgres::{PgPool, PgPoolOptions},
+    types::Json,
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+use turborepo_storage_adapter::{
+    ArtifactMetadata, ArtifactStream, ObjectEntries, ObjectEntry, ObjectInfo, StorageAdapter,
+    StorageAdapterError,
+};
+
+const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+/// Number of artifacts [`PostgresStorageAdapter::list`] fetches per query.
+const LIST_PAGE_SIZE: i64 = 1000;
+
+/// Serializes the creation of the tables by several servers starting at once.
+const SCHEMA_LOCK: i64 = 0x7475_7262_6f72_6570;
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS turborepo_artifacts (
+         team_id TEXT NOT NULL,
+         hash TEXT NOT NULL,
+         size BIGINT NOT NULL,
+         metadata JSONB,
+         created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+         last_accessed TIMESTAMPTZ NOT NULL DEFAULT now(),
+         PRIMARY KEY (team_id, hash)
+     )",
+    "CREATE INDEX IF NOT EXISTS turborepo_artifacts_last_accessed
+         ON turborepo_artifacts (last_accessed)",
+    "CREATE TABLE IF NOT EXISTS turborepo_artifact_chunks (
+         team_id TEXT NOT NULL,
+         hash TEXT NOT NULL,
+         seq INTEGER NOT NULL,
+         data BYTEA NOT NULL,
+         PRIMARY KEY (team_id, hash, seq),
+         FOREIGN KEY (team_id, hash) REFERENCES turborepo_artifacts ON DELETE CASCADE
+     )",
+];
+
+/// Stores the artifacts in PostgreSQL, as `bytea` chunks of the
+/// `turborepo_artifact_chunks` table, next to their size and metadata in
+/// `turborepo_artifacts`.
+///
+/// Artifacts are spooled to a file of the system's temporary directory on
+/// their way in and out, so that a connection of the pool is only held while
+/// their chunks are written or read, in a transaction, rather than for as long
+/// as the client takes to send or receive them. Downloads update
+/// `last_accessed`, so stale artifacts can be pruned with plain SQL, e.g.
+///
+/// ```sql
+/// DELETE FROM turborepo_artifacts WHERE last_accessed < now() - interval '30 days';
+/// ```
+pub struct PostgresStorageAdapter {
+    pool: PgPool,
+    chunk_size: usize,
+}
+
+impl PostgresStorageAdapter {
+    pub fn builder() -> PostgresStorageAdapterBuilder {
+        PostgresStorageAdapterBuilder {
+            url: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+#[async_trait]
+impl StorageAdapter for PostgresStorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let artifact = self.get_(path).await?;
+
+        hyper::body::to_bytes(artifact.body)
+            .await
+            .map_err(|err| StorageAdapterError::Other(err.into()))
+    }
+
+    async fn get_(&self, path: PathBuf) -> Result<ArtifactStream, StorageAdapterError> {
+        let (team_id, hash) = split(&path)?;
+
+        // The chunks are read from a snapshot, so an artifact replaced while
+        // it's spooled is still read whole.
+        let mut transaction = self.pool.begin().await.map_err(classify)?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .execute(&mut *transaction)
+            .await
+            .map_err(classify)?;
+
+        // Best effort: the access time isn't updated while the artifact is
+        // being uploaded again, rather than waiting for the upload to end.
+        // Taking the snapshot, this comes first, so that an upload committed
+        // meanwhile can't make it fail.
+        sqlx::query(
+            "UPDATE turborepo_artifacts SET last_accessed = now() \
+             WHERE (team_id, hash) IN ( \
+                 SELECT team_id, hash FROM turborepo_artifacts \
+                 WHERE team_id = $1 AND hash = $2 \
+                 FOR UPDATE SKIP LOCKED \
+             )",
+        )
+        .bind(&team_id)
+        .bind(&hash)
+        .execute(&mut *transaction)
+        .await
+        .map_err(classify)?;
+
+        let size: i64 = sqlx::query_scalar(
+            "SELECT size FROM turborepo_artifacts WHERE team_id = $1 AND hash = $2",
+        )
+        .bind(&team_id)
+        .bind(&hash)
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(classify)?
+        .ok_or_else(|| not_found(&path))?;
+
+        let mut spool = fs::File::from_std(tempfile::tempfile()?);
+
+        let mut chunks = sqlx::query_scalar::<_, Vec<u8>>(
+            "SELECT data FROM turborepo_artifact_chunks \
+             WHERE team_id = $1 AND hash = $2 \
+             ORDER BY seq",
+        )
+        .bind(&team_id)
+        .bind(&hash)
+        .fetch(&mut *transaction);
+
+        while let Some(chunk) = chunks.try_next().await.map_err(classify)? {
+            spool.write_all(&chunk).await?;
+        }
+        drop(chunks);
+
+        transaction.commit().await.map_err(classify)?;
+
+        spool.flush().await?;
+        spool.rewind().await?;
+
+        Ok(ArtifactStream {
+            body: Body::wrap_stream(ReaderStream::new(spool)),
+            size: size as u64,
+        })
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
+        let (team_id, hash) = split(&path)?;
+
+        sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM turborepo_artifacts WHERE team_id = $1 AND hash = $2)",
+        )
+        .bind(team_id)
+        .bind(hash)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(classify)
+    }
+
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        let (team_id, hash) = split(&path)?;
+
+        let row: Option<(i64, i64)> = sqlx::query_as(
+            "SELECT size, (extract(epoch FROM created_at) * 1000)::BIGINT \
+             FROM turborepo_artifacts \
+             WHERE team_id = $1 AND hash = $2",
+        )
+        .bind(team_id)
+        .bind(hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(classify)?;
+
+        Ok(row.map(|(size, created_at)| ObjectInfo {
+            size: size as u64,
+            last_modified: Some(UNIX_EPOCH + Duration::from_millis(created_at as u64)),
+        }))
+    }
+
+    /// Lists the artifacts by team and hash, a page at a time, each page
+    /// starting after the last artifact of the previous one.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let prefix = prefix.to_string_lossy().into_owned();
+
+        futures::stream::try_unfold(
+            Some(None),
+            move |after: Option<Option<(String, String)>>| {
+                let prefix = prefix.clone();
+
+                async move {
+                    let Some(after) = after else {
+                        return Ok(None);
+                    };
+                    let (after_team_id, after_hash) = after.unzip();
+
+                    let rows: Vec<(String, String, i64, i64)> = sqlx::query_as(
+                        "SELECT team_id, hash, size, \
+                             (extract(epoch FROM created_at) * 1000)::BIGINT \
+                         FROM turborepo_artifacts \
+                         WHERE starts_with(team_id || '/' || hash, $1) \
+                             AND ($2::TEXT IS NULL OR (team_id, hash) > ($2, $3)) \
+                         ORDER BY team_id, hash \
+                         LIMIT $4",
+                    )
+                    .bind(prefix)
+                    .bind(after_team_id)
+                    .bind(after_hash)
+                    .bind(LIST_PAGE_SIZE)
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(classify)?;
+
+                    let after = match rows.len() as i64 {
+                        LIST_PAGE_SIZE => rows
+                            .last()
+                            .map(|(team_id, hash, _, _)| Some((team_id.clone(), hash.clone()))),
+                        _ => None,
+                    };
+
+                    let entries = rows.into_iter().map(|(team_id, hash, size, created_at)| {
+                        Ok(ObjectEntry {
+                            path: Path::new(&team_id).join(hash),
+                            info: ObjectInfo {
+                                size: size as u64,
+                                last_modified: Some(
+                                    UNIX_EPOCH + Duration::from_millis(created_at as u64),
+                                ),
+                            },
+                        })
+                    });
+
+                    Ok::<_, StorageAdapterError>(Some((futures::stream::iter(entries), after)))
+                }
+            },
+        )
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Removes the row of the artifact, its chunks and metadata with it.
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        let (team_id, hash) = split(&path)?;
+
+        sqlx::query("DELETE FROM turborepo_artifacts WHERE team_id = $1 AND hash = $2")
+            .bind(team_id)
+            .bind(hash)
+            .execute(&self.pool)
+            .await
+            .map_err(classify)?;
+
+        Ok(())
+    }
+
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        self.upload_(path, artifact.into()).await
+    }
+
+    async fn upload_<'a>(
+        &self,
+        path: PathBuf,
+        mut artifact: Body,
+    ) -> Result<(), StorageAdapterError> {
+        let (team_id, hash) = split(&path)?;
+
+        // Nothing is written until the artifact was received whole.
+        let mut spool = fs::File::from_std(tempfile::tempfile()?);
+
+        let mut size = 0;
+        while let Some(chunk) = artifact.next().await {
+            let chunk = chunk.map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+            spool.write_all(&chunk).await?;
+            size += chunk.len() as i64;
+        }
+        spool.flush().await?;
+        spool.rewind().await?;
+
+        // Nothing is visible until the last chunk is written; a failed upload
+        // is rolled back when the transaction is dropped.
+        let mut transaction = self.pool.begin().await.map_err(classify)?;
+
+        sqlx::query(
+            "INSERT INTO turborepo_artifacts (team_id, hash, size) VALUES ($1, $2, $3) \
+             ON CONFLICT (team_id, hash) DO UPDATE SET \
+                 size = $3, metadata = NULL, created_at = now(), last_accessed = now()",
+        )
+        .bind(&team_id)
+        .bind(&hash)
+        .bind(size)
+        .execute(&mut *transaction)
+        .await
+        .map_err(classify)?;
+
+        sqlx::query("DELETE FROM turborepo_artifact_chunks WHERE team_id = $1 AND hash = $2")
+            .bind(&team_id)
+            .bind(&hash)
+            .execute(&mut *transaction)
+            .await
+            .map_err(classify)?;
+
+        let mut data = Vec::with_capacity(self.chunk_size);
+        let mut seq = 0;
+
+        loop {
+            data.clear();
+            (&mut spool)
+                .take(self.chunk_size as u64)
+                .read_to_end(&mut data)
+                .await?;
+
+            if data.is_empty() {
+                break;
+            }
+
+            sqlx::query(
+                "INSERT INTO turborepo_artifact_chunks (team_id, hash, seq, data) \
+                 VALUES ($1, $2, $3, $4)",
+            )
+            .bind(&team_id)
+            .bind(&hash)
+            .bind(seq)
+            .bind(&data[..])
+            .execute(&mut *transaction)
+            .await
+            .map_err(classify)?;
+
+            seq += 1;
+        }
+
+        transaction.commit().await.map_err(classify)
+    }
+
+    async fn get_metadata(
+        &self,
+        path: PathBuf,
+    ) -> Result<Option<ArtifactMetadata>, StorageAdapterError> {
+        let (team_id, hash) = split(&path)?;
+
+        let metadata: Option<Option<serde_json::Value>> = sqlx::query_scalar(
+            "SELECT metadata FROM turborepo_artifacts WHERE team_id = $1 AND hash = $2",
+        )
+        .bind(team_id)
+        .bind(hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(classify)?;
+
+        metadata
+            .flatten()
+            .map(|metadata| serde_json::from_value(metadata).map_err(std::io::Error::from))
+            .transpose()
+            .map_err(StorageAdapterError::from)
+    }
+
+    /// Metadata is stored in the row of its artifact, which must exist.
+    async fn put_metadata(
+        &self,
+        path: PathBuf,
+        metadata: &ArtifactMetadata,
+    ) -> Result<(), StorageAdapterError> {
+        let (team_id, hash) = split(&path)?;
+
+        let updated = sqlx::query(
+            "UPDATE turborepo_artifacts SET metadata = $3 WHERE team_id = $1 AND hash = $2",
+        )
+        .bind(team_id)
+        .bind(hash)
+        .bind(Json(metadata))
+        .execute(&self.pool)
+        .await
+        .map_err(classify)?
+        .rows_affected();
+
+        match updated {
+            0 => Err(not_found(&path)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        let (team_id, hash) = split(&path)?;
+
+        sqlx::query(
+            "UPDATE turborepo_artifacts SET metadata = NULL \
+             WHERE team_id = $1 AND hash = $2 AND metadata IS NOT NULL",
+        )
+        .bind(team_id)
+        .bind(hash)
+        .execute(&self.pool)
+        .await
+        .map_err(classify)?;
+
+        Ok(())
+    }
+}
+
+/// Splits the `<team>/<hash>` path of an artifact.
+fn split(path: &Path) -> Result<(String, String), StorageAdapterError> {
+    path.to_str()
+        .and_then(|path| path.split_once('/'))
+        .map(|(team_id, hash)| (team_id.to_string(), hash.to_string()))
+        .ok_or_else(|| {
+            StorageAdapterError::InvalidKey(format!("{:?} is not <team>/<hash>", path).into())
+        })
+}
+
+fn not_found(path: &Path) -> StorageAdapterError {
+    StorageAdapterError::NotFound(format!("{:?} is not stored", path).into())
+}
+
+fn classify(err: sqlx::Error) -> StorageAdapterError {
+    let code = match &err {
+        sqlx::Error::RowNotFound => return StorageAdapterError::NotFound(err.into()),
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => return StorageAdapterError::Unavailable(err.into()),
+        sqlx::Error::Database(database) => database.code().unwrap_or_default().into_owned(),
+        _ => return StorageAdapterError::Other(err.into()),
+    };
+
+    // See the "PostgreSQL Error Codes" appendix.
+    match code.as_str() {
+        // Disk full, out of memory, program limit exceeded.
+        "53100" | "53200" | "54000" => StorageAdapterError::QuotaExceeded(err.into()),
+        // Invalid authorization, insufficient privilege.
+        code if code.starts_with("28") || code == "42501" => {
+            StorageAdapterError::PermissionDenied(err.into())
+        }
+        // Connection exception, shutdown.
+        code if code.starts_with("08") || code.starts_with("57P") => {
+            StorageAdapterError::Unavailable(err.into())
+        }
+        // Serialization failure, deadlock, too many connections.
+        "40001" | "40P01" | "53300" => StorageAdapterError::Unavailable(err.into()),
+        _ => StorageAdapterError::Other(err.into()),
+    }
+}
+
+pub struct PostgresStorageAdapterBuilder {
+    url: Option<String>,
+    max_connections: u32,
+    chunk_size: usize,
+}
+
+impl PostgresStorageAdapterBuilder {
+    /// Connects to the database and creates the tables if needed.
+    pub async fn build(&self) -> Result<PostgresStorageAdapter, sqlx::Error> {
+        let pool = PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .connect(self.url.as_deref().unwrap())
+            .await?;
+
+        let mut transaction = pool.begin().await?;
+
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(SCHEMA_LOCK)
+            .execute(&mut *transaction)
+            .await?;
+
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(PostgresStorageAdapter {
+            pool,
+            chunk_size: self.chunk_size,
+        })
+    }
+
+    /// Connection URL, as `postgres://<USER>:<PASSWORD>@<HOST>[:<PORT>]/<DATABASE>`.
+    pub fn with_url(&mut self, url: String) -> &mut Self {
+        self.url.replace(url);
+
+        self
+    }
+
+    /// Size of the connection pool; 10 by default.
+    pub fn with_max_connections(&mut self, max_connections: u32) -> &mut Self {
+        self.max_connections = max_connections;
+
+        self
+    }
+
+    /// Size, in bytes, of the chunks artifacts are stored in; 1 MiB by
+    /// default.
+    pub fn with_chunk_size(&mut self, chunk_size: usize) -> &mut Self {
+        self.chunk_size = chunk_size.max(1);
+
+        self
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    io::{Read, Seek},
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use hyper::Body;
+use redb::{Database, ReadableTable, TableDefinition};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{mpsc, oneshot},
+};
+use turborepo_storage_adapter::{
+    ArtifactMetadata, ArtifactStream, ObjectEntries, ObjectEntry, ObjectInfo, StorageAdapter,
+    StorageAdapterError,
+};
+
+/// Size, last modification time in milliseconds and number of chunks of the
+/// artifacts.
+const OBJECTS: TableDefinition<&str, (u64, u64, u32)> = TableDefinition::new("objects");
+/// Chunks of the artifacts, by path and position.
+const CHUNKS: TableDefinition<(&str, u32), &[u8]> = TableDefinition::new("chunks");
+/// Metadata of the artifacts, as JSON.
+const METADATA: TableDefinition<&str, &str> = TableDefinition::new("metadata");
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+/// Number of artifacts [`RedbStorageAdapter::list`] reads per transaction.
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// Keeps the artifacts and their metadata in a single [redb] file, for
+/// single-binary deployments.
+///
+/// Uploads are spooled to a temporary file next to the database, then written
+/// to it in a single transaction: a crash or an interrupted upload leaves the
+/// previous artifact, if any, in place. Readers see a snapshot of the database and never wait
+/// for writers.
+///
+/// [redb]: https://www.redb.org
+pub struct RedbStorageAdapter {
+    path: PathBuf,
+    database: Arc<Database>,
+}
+
+impl RedbStorageAdapter {
+    pub fn builder() -> RedbStorageAdapterBuilder {
+        RedbStorageAdapterBuilder { path: None }
+    }
+
+    /// Runs `f` on the database, off the async runtime.
+    async fn run<T, F>(&self, f: F) -> Result<T, StorageAdapterError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database) -> Result<T, Error> + Send + 'static,
+    {
+        let database = self.database.clone();
+
+        tokio::task::spawn_blocking(move || f(&database))
+            .await
+            .map_err(|err| StorageAdapterError::Other(err.into()))?
+            .map_err(classify)
+    }
+}
+
+#[async_trait]
+impl StorageAdapter for RedbStorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let artifact = self.get_(path).await?;
+
+        hyper::body::to_bytes(artifact.body)
+            .await
+            .map_err(|err| StorageAdapterError::Other(err.into()))
+    }
+
+    async fn get_(&self, path: PathBuf) -> Result<ArtifactStream, StorageAdapterError> {
+        let key = key(&path);
+        let database = self.database.clone();
+        let (size_sender, size_receiver) = oneshot::channel();
+        let (chunk_sender, chunk_receiver) = mpsc::channel(4);
+
+        tokio::task::spawn_blocking(move || {
+            let mut size_sender = Some(size_sender);
+
+            if let Err(err) = read_chunks(&database, &key, &mut size_sender, &chunk_sender) {
+                let err = classify(err);
+
+                match size_sender {
+                    Some(size_sender) => {
+                        let _ = size_sender.send(Err(err));
+                    }
+                    None => {
+                        let _ = chunk_sender.blocking_send(Err(err));
+                    }
+                }
+            }
+        });
+
+        let size = size_receiver
+            .await
+            .map_err(|err| StorageAdapterError::Other(err.into()))??
+            .ok_or_else(|| not_found(&path))?;
+
+        let chunks = futures::stream::unfold(chunk_receiver, |mut chunk_receiver| async move {
+            chunk_receiver
+                .recv()
+                .await
+                .map(|chunk| (chunk, chunk_receiver))
+        });
+
+        Ok(ArtifactStream {
+            body: Body::wrap_stream(chunks),
+            size,
+        })
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
+        Ok(self.stat(path).await?.is_some())
+    }
+
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        let key = key(&path);
+
+        self.run(move |database| {
+            let objects = database.begin_read()?.open_table(OBJECTS)?;
+            let object = objects.get(key.as_str())?.map(|object| {
+                let (size, last_modified, _) = object.value();
+
+                ObjectInfo {
+                    size,
+                    last_modified: Some(UNIX_EPOCH + Duration::from_millis(last_modified)),
+                }
+            });
+
+            Ok(object)
+        })
+        .await
+    }
+
+    /// Lists the artifacts in key order, reading a page at a time, each in
+    /// its own transaction.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let prefix = key(&prefix);
+
+        futures::stream::try_unfold(Some(None), move |after: Option<Option<String>>| {
+            let prefix = prefix.clone();
+
+            async move {
+                let Some(after) = after else {
+                    return Ok(None);
+                };
+
+                let page = self
+                    .run(move |database| {
+                        let objects = database.begin_read()?.open_table(OBJECTS)?;
+                        let range = match &after {
+                            Some(after) => objects.range::<&str>((
+                                Bound::Excluded(after.as_str()),
+                                Bound::Unbounded,
+                            ))?,
+                            None => objects.range::<&str>(prefix.as_str()..)?,
+                        };
+
+                        let mut page = vec![];
+                        for object in range.take(LIST_PAGE_SIZE) {
+                            let (key, object) = object?;
+                            if !key.value().starts_with(&prefix) {
+                                break;
+                            }
+
+                            let (size, last_modified, _) = object.value();
+                            page.push(ObjectEntry {
+                                path: key.value().into(),
+                                info: ObjectInfo {
+                                    size,
+                                    last_modified: Some(
+                                        UNIX_EPOCH + Duration::from_millis(last_modified),
+                                    ),
+                                },
+                            });
+                        }
+
+                        Ok(page)
+                    })
+                    .await?;
+
+                let after = match page.len() {
+                    LIST_PAGE_SIZE => page
+                        .last()
+                        .map(|entry| Some(entry.path.to_string_lossy().into_owned())),
+                    _ => None,
+                };
+
+                Ok::<_, StorageAdapterError>(Some((
+                    futures::stream::iter(page.into_iter().map(Ok)),
+                    after,
+                )))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        let key = key(&path);
+
+        self.run(move |database| {
+            let transaction = database.begin_write()?;
+
+            {
+                let mut objects = transaction.open_table(OBJECTS)?;
+                let mut chunks = transaction.open_table(CHUNKS)?;
+
+                let count = objects.remove(key.as_str())?.map(|object| object.value().2);
+                for seq in 0..count.unwrap_or_default() {
+                    chunks.remove((key.as_str(), seq))?;
+                }
+                transaction.open_table(METADATA)?.remove(key.as_str())?;
+            }
+
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        self.upload_(path, artifact.into()).await
+    }
+
+    /// The body is spooled to a temporary file next to the database first,
+    /// so that a slow client doesn't hold up the other writes.
+    async fn upload_<'a>(
+        &self,
+        path: PathBuf,
+        mut artifact: Body,
+    ) -> Result<(), StorageAdapterError> {
+        let mut spool = fs::File::from_std(tempfile::tempfile_in(spool_dir(&self.path))?);
+
+        while let Some(chunk) = artifact.next().await {
+            let chunk = chunk.map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+            spool.write_all(&chunk).await?;
+        }
+        spool.flush().await?;
+
+        let key = key(&path);
+        let mut spool = spool.into_std().await;
+
+        self.run(move |database| {
+            spool.rewind()?;
+
+            let transaction = database.begin_write()?;
+
+            {
+                let mut objects = transaction.open_table(OBJECTS)?;
+                let mut chunks = transaction.open_table(CHUNKS)?;
+                let mut metadata = transaction.open_table(METADATA)?;
+
+                let previous = objects.remove(key.as_str())?.map(|object| object.value().2);
+                for seq in 0..previous.unwrap_or_default() {
+                    chunks.remove((key.as_str(), seq))?;
+                }
+                metadata.remove(key.as_str())?;
+
+                let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+                let mut seq = 0;
+                let mut size = 0;
+                loop {
+                    chunk.clear();
+                    (&mut spool)
+                        .take(CHUNK_SIZE as u64)
+                        .read_to_end(&mut chunk)?;
+                    if chunk.is_empty() {
+                        break;
+                    }
+
+                    chunks.insert((key.as_str(), seq), &chunk[..])?;
+                    seq += 1;
+                    size += chunk.len() as u64;
+                }
+
+                objects.insert(key.as_str(), (size, now(), seq))?;
+            }
+
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_metadata(
+        &self,
+        path: PathBuf,
+    ) -> Result<Option<ArtifactMetadata>, StorageAdapterError> {
+        let key = key(&path);
+
+        let metadata = self
+            .run(move |database| {
+                let metadata = database.begin_read()?.open_table(METADATA)?;
+                let metadata = metadata
+                    .get(key.as_str())?
+                    .map(|metadata| metadata.value().to_string());
+
+                Ok(metadata)
+            })
+            .await?;
+
+        metadata
+            .map(|metadata| serde_json::from_str(&metadata).map_err(std::io::Error::from))
+            .transpose()
+            .map_err(StorageAdapterError::from)
+    }
+
+    /// Metadata is stored along with its artifact, which must exist.
+    async fn put_metadata(
+        &self,
+        path: PathBuf,
+        metadata: &ArtifactMetadata,
+    ) -> Result<(), StorageAdapterError> {
+        let key = key(&path);
+        let metadata = serde_json::to_string(metadata).map_err(std::io::Error::from)?;
+
+        let stored = self
+            .run(move |database| {
+                let transaction = database.begin_write()?;
+
+                let stored = transaction
+                    .open_table(OBJECTS)?
+                    .get(key.as_str())?
+                    .is_some();
+                if stored {
+                    transaction
+                        .open_table(METADATA)?
+                        .insert(key.as_str(), metadata.as_str())?;
+                }
+
+                transaction.commit()?;
+
+                Ok(stored)
+            })
+            .await?;
+
+        match stored {
+            true => Ok(()),
+            false => Err(not_found(&path)),
+        }
+    }
+
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        let key = key(&path);
+
+        self.run(move |database| {
+            let transaction = database.begin_write()?;
+            transaction.open_table(METADATA)?.remove(key.as_str())?;
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Streams the chunks of the artifact stored at `key` to `chunk_sender`,
+/// after sending its size, or `None` if there is none, to `size_sender`.
+fn read_chunks(
+    database: &Database,
+    key: &str,
+    size_sender: &mut Option<oneshot::Sender<Result<Option<u64>, StorageAdapterError>>>,
+    chunk_sender: &mpsc::Sender<Result<Bytes, StorageAdapterError>>,
+) -> Result<(), Error> {
+    // Reads a snapshot of the database, so the artifact can't be replaced
+    // while it's streamed.
+    let transaction = database.begin_read()?;
+
+    let object = transaction
+        .open_table(OBJECTS)?
+        .get(key)?
+        .map(|object| object.value());
+
+    if let Some(size_sender) = size_sender.take() {
+        let _ = size_sender.send(Ok(object.map(|(size, _, _)| size)));
+    }
+
+    let Some((_, _, count)) = object else {
+        return Ok(());
+    };
+
+    let chunks = transaction.open_table(CHUNKS)?;
+    for chunk in chunks.range((key, 0)..(key, count))? {
+        let (_, chunk) = chunk?;
+
+        // The response was dropped.
+        if chunk_sender
+            .blocking_send(Ok(Bytes::copy_from_slice(chunk.value())))
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Directory uploads are spooled to, the one of the database so that they
+/// don't fill up a smaller temporary file system.
+fn spool_dir(database: &Path) -> &Path {
+    match database.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn not_found(path: &Path) -> StorageAdapterError {
+    StorageAdapterError::NotFound(format!("{:?} is not stored", path).into())
+}
+
+/// A redb error, boxed as it's large.
+struct Error(Box<redb::Error>);
+
+impl<E: Into<redb::Error>> From<E> for Error {
+    fn from(err: E) -> Self {
+        Error(Box::new(err.into()))
+    }
+}
+
+fn classify(Error(err): Error) -> StorageAdapterError {
+    match *err {
+        redb::Error::Io(err) => err.into(),
+        redb::Error::DatabaseAlreadyOpen => StorageAdapterError::Unavailable(err.into()),
+        redb::Error::ValueTooLarge(_) => StorageAdapterError::QuotaExceeded(err.into()),
+        err => StorageAdapterError::Other(err.into()),
+    }
+}
+
+pub struct RedbStorageAdapterBuilder {
+    path: Option<PathBuf>,
+}
+
+impl RedbStorageAdapterBuilder {
+    /// Opens the database, creating it and its tables if needed.
+    pub async fn build(&self) -> Result<RedbStorageAdapter, StorageAdapterError> {
+        let path = self.path.clone().unwrap();
+
+        let database = path.clone();
+
+        let database = tokio::task::spawn_blocking(move || {
+            let database = Database::create(database)?;
+
+            let transaction = database.begin_write()?;
+            transaction.open_table(OBJECTS)?;
+            transaction.open_table(CHUNKS)?;
+            transaction.open_table(METADATA)?;
+            transaction.commit()?;
+
+            Ok::<_, Error>(database)
+        })
+        .await
+        .map_err(|err| StorageAdapterError::Other(err.into()))?
+        .map_err(classify)?;
+
+        Ok(RedbStorageAdapter {
+            path,
+            database: Arc::new(database),
+        })
+    }
+
+    /// Path of the database file.
+    pub fn with_path(&mut self, path: PathBuf) -> &mut Self {
+        self.path.replace(path);
+
+        self
+    }
+}
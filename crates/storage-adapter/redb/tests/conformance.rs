@@ -0,0 +1,13 @@
+use turborepo_redb_storage_adapter::RedbStorageAdapter;
+
+#[tokio::test]
+async fn conformance() {
+    let directory = tempfile::tempdir().unwrap();
+    let storage = RedbStorageAdapter::builder()
+        .with_path(directory.path().join("turborepo.redb"))
+        .build()
+        .await
+        .unwrap();
+
+    turborepo_storage_adapter_tests::run(&storage).await;
+}
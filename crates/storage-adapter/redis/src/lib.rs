@@ -0,0 +1,328 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::{StreamExt, TryStreamExt};
+use hyper::{body::HttpBody, Body};
+use redis::{aio::ConnectionManager, ErrorKind, RedisError};
+use turborepo_storage_adapter::{
+    is_metadata_path, metadata_path, ArtifactMetadata, ObjectEntries, ObjectEntry, ObjectInfo,
+    StorageAdapter, StorageAdapterError,
+};
+
+const DEFAULT_URL: &str = "redis://127.0.0.1:6379";
+/// Prepended to the keys by default, so they don't collide with other data of
+/// a shared server.
+const DEFAULT_KEY_PREFIX: &str = "turborepo:";
+/// Artifacts larger than this aren't stored by default. Redis keeps values in
+/// memory, so it suits the many small artifacts of type-check and lint tasks
+/// rather than build outputs.
+const DEFAULT_MAX_ARTIFACT_SIZE: usize = 16 * 1024 * 1024;
+/// Number of keys `SCAN` is asked to walk through at once.
+const SCAN_COUNT: usize = 1000;
+
+/// Stores the artifacts in Redis, or any server speaking its protocol, such as
+/// KeyDB or Valkey. Artifacts expire after the configured TTL, if any.
+pub struct RedisStorageAdapter {
+    connection: ConnectionManager,
+    key_prefix: String,
+    ttl: Option<Duration>,
+    max_artifact_size: usize,
+}
+
+impl RedisStorageAdapter {
+    pub fn builder() -> RedisStorageAdapterBuilder {
+        RedisStorageAdapterBuilder {
+            url: DEFAULT_URL.into(),
+            key_prefix: DEFAULT_KEY_PREFIX.into(),
+            ttl: None,
+            max_artifact_size: DEFAULT_MAX_ARTIFACT_SIZE,
+        }
+    }
+
+    fn key(&self, path: &Path) -> String {
+        format!("{}{}", self.key_prefix, path.to_string_lossy())
+    }
+
+    fn too_large(&self, size: usize) -> StorageAdapterError {
+        StorageAdapterError::QuotaExceeded(
+            format!(
+                "{} bytes exceed the {} bytes artifacts are limited to",
+                size, self.max_artifact_size
+            )
+            .into(),
+        )
+    }
+}
+
+#[async_trait]
+impl StorageAdapter for RedisStorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let artifact: Option<Vec<u8>> = redis::cmd("GET")
+            .arg(self.key(&path))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(classify)?;
+
+        artifact.map(Bytes::from).ok_or_else(|| {
+            StorageAdapterError::NotFound(format!("{:?} is not stored", path).into())
+        })
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
+        redis::cmd("EXISTS")
+            .arg(self.key(&path))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(classify)
+    }
+
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        let key = self.key(&path);
+        let (exists, size): (bool, u64) = redis::pipe()
+            .cmd("EXISTS")
+            .arg(&key)
+            .cmd("STRLEN")
+            .arg(&key)
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(classify)?;
+
+        Ok(exists.then_some(ObjectInfo {
+            size,
+            last_modified: None,
+        }))
+    }
+
+    /// Lists the artifacts with `SCAN`, fetching the next page once the
+    /// previous one was consumed. As with `SCAN`, an artifact may be listed
+    /// more than once, and those stored or removed meanwhile may be missed.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let pattern = format!("{}*", escape_glob(&self.key(&prefix)));
+
+        futures::stream::try_unfold(Some(0), move |cursor| {
+            let pattern = pattern.clone();
+
+            async move {
+                let Some(cursor) = cursor else {
+                    return Ok(None);
+                };
+
+                let (cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(pattern)
+                    .arg("COUNT")
+                    .arg(SCAN_COUNT)
+                    .query_async(&mut self.connection.clone())
+                    .await
+                    .map_err(classify)?;
+
+                let keys = keys
+                    .into_iter()
+                    .filter(|key| !is_metadata_path(Path::new(key)))
+                    .collect::<Vec<_>>();
+
+                let mut sizes = redis::pipe();
+                for key in &keys {
+                    sizes.cmd("STRLEN").arg(key);
+                }
+                let sizes: Vec<u64> = match keys.is_empty() {
+                    true => vec![],
+                    false => sizes
+                        .query_async(&mut self.connection.clone())
+                        .await
+                        .map_err(classify)?,
+                };
+
+                let entries = keys
+                    .iter()
+                    .zip(sizes)
+                    .filter_map(|(key, size)| {
+                        Some(Ok(ObjectEntry {
+                            path: key.strip_prefix(&self.key_prefix)?.into(),
+                            info: ObjectInfo {
+                                size,
+                                last_modified: None,
+                            },
+                        }))
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok::<_, StorageAdapterError>(Some((
+                    futures::stream::iter(entries),
+                    (cursor != 0).then_some(cursor),
+                )))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        redis::cmd("DEL")
+            .arg(self.key(&metadata_path(&path)))
+            .arg(self.key(&path))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(classify)
+    }
+
+    /// Reads the sidecar along with the artifact's existence, as the sidecar,
+    /// written after the artifact, also expires after it.
+    async fn get_metadata(
+        &self,
+        path: PathBuf,
+    ) -> Result<Option<ArtifactMetadata>, StorageAdapterError> {
+        let (exists, metadata): (bool, Option<Vec<u8>>) = redis::pipe()
+            .atomic()
+            .cmd("EXISTS")
+            .arg(self.key(&path))
+            .cmd("GET")
+            .arg(self.key(&metadata_path(&path)))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(classify)?;
+
+        match metadata.filter(|_| exists) {
+            Some(metadata) => Ok(Some(
+                serde_json::from_slice(&metadata).map_err(std::io::Error::from)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        redis::cmd("DEL")
+            .arg(self.key(&metadata_path(&path)))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(classify)
+    }
+
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        if artifact.len() > self.max_artifact_size {
+            return Err(self.too_large(artifact.len()));
+        }
+
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(self.key(&path)).arg(artifact.as_ref());
+
+        if let Some(ttl) = self.ttl {
+            cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+        }
+
+        cmd.query_async(&mut self.connection.clone())
+            .await
+            .map_err(classify)
+    }
+
+    async fn upload_<'a>(
+        &self,
+        path: PathBuf,
+        mut artifact: Body,
+    ) -> Result<(), StorageAdapterError> {
+        // Rejects artifacts announcing a larger `Content-Length` upfront.
+        let size_hint = artifact.size_hint();
+        if size_hint.lower() > self.max_artifact_size as u64 {
+            return Err(self.too_large(size_hint.lower() as usize));
+        }
+
+        let mut buffer = BytesMut::with_capacity(size_hint.exact().unwrap_or_default() as usize);
+
+        // Stops reading as soon as the artifact is known to be too large.
+        while let Some(chunk) = artifact.next().await {
+            let chunk = chunk.map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+            buffer.extend_from_slice(&chunk);
+
+            if buffer.len() > self.max_artifact_size {
+                return Err(self.too_large(buffer.len()));
+            }
+        }
+
+        self.upload(path, buffer.freeze()).await
+    }
+}
+
+/// Escapes the characters `SCAN` patterns give a meaning to.
+fn escape_glob(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+
+    for c in key.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '^' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn classify(err: RedisError) -> StorageAdapterError {
+    if err.is_io_error() || err.is_timeout() || err.is_connection_dropped() {
+        return StorageAdapterError::Unavailable(err.into());
+    }
+
+    match err.kind() {
+        ErrorKind::AuthenticationFailed => StorageAdapterError::PermissionDenied(err.into()),
+        ErrorKind::BusyLoadingError | ErrorKind::TryAgain | ErrorKind::ClusterDown => {
+            StorageAdapterError::Unavailable(err.into())
+        }
+        _ if err.code() == Some("OOM") => StorageAdapterError::QuotaExceeded(err.into()),
+        _ => StorageAdapterError::Other(err.into()),
+    }
+}
+
+pub struct RedisStorageAdapterBuilder {
+    url: String,
+    key_prefix: String,
+    ttl: Option<Duration>,
+    max_artifact_size: usize,
+}
+
+impl RedisStorageAdapterBuilder {
+    /// Connects to the server; the connection is reestablished whenever it
+    /// drops.
+    pub async fn build(&self) -> Result<RedisStorageAdapter, RedisError> {
+        let client = redis::Client::open(self.url.as_str())?;
+
+        Ok(RedisStorageAdapter {
+            connection: ConnectionManager::new(client).await?,
+            key_prefix: self.key_prefix.clone(),
+            ttl: self.ttl,
+            max_artifact_size: self.max_artifact_size,
+        })
+    }
+
+    /// URL of the server, as `redis://[<USER>][:<PASSWORD>@]<HOST>[:<PORT>][/<DB>]`.
+    /// Defaults to `redis://127.0.0.1:6379`.
+    pub fn with_url(&mut self, url: String) -> &mut Self {
+        self.url = url;
+
+        self
+    }
+
+    /// Prefix of the keys artifacts are stored under; `turborepo:` by default.
+    pub fn with_key_prefix(&mut self, key_prefix: String) -> &mut Self {
+        self.key_prefix = key_prefix;
+
+        self
+    }
+
+    /// Time after which artifacts expire. They never do by default.
+    pub fn with_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.ttl.replace(ttl);
+
+        self
+    }
+
+    /// Size, in bytes, above which artifacts are rejected; 16 MiB by default.
+    pub fn with_max_artifact_size(&mut self, max_artifact_size: usize) -> &mut Self {
+        self.max_artifact_size = max_artifact_size;
+
+        self
+    }
+}
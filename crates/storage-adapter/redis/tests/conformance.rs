@@ -0,0 +1,116 @@
+//! Runs against the Redis server at `TURBOREPO_TEST_REDIS_URL`. For instance,
+//! with a local container:
+//!
+//! ```sh
+//! docker run -p 6379:6379 redis
+//! TURBOREPO_TEST_REDIS_URL=redis://127.0.0.1:6379 \
+//!     cargo test -p turborepo-redis-storage-adapter -- --ignored
+//! ```
+
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use hyper::Body;
+use turborepo_redis_storage_adapter::RedisStorageAdapter;
+use turborepo_storage_adapter::{ArtifactMetadata, StorageAdapter, StorageAdapterError};
+
+fn url() -> String {
+    std::env::var("TURBOREPO_TEST_REDIS_URL").expect("TURBOREPO_TEST_REDIS_URL is not set")
+}
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_REDIS_URL"]
+async fn conformance() {
+    let storage = RedisStorageAdapter::builder()
+        .with_url(url())
+        .build()
+        .await
+        .unwrap();
+
+    turborepo_storage_adapter_tests::run(&storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_REDIS_URL"]
+async fn key_prefix() {
+    let storage = RedisStorageAdapter::builder()
+        .with_url(url())
+        .with_key_prefix("turborepo-test:".into())
+        .build()
+        .await
+        .unwrap();
+    storage
+        .upload(PathBuf::from("team/prefixed"), Bytes::from("artifact"))
+        .await
+        .unwrap();
+
+    let mut connection = redis::Client::open(url())
+        .unwrap()
+        .get_async_connection()
+        .await
+        .unwrap();
+    let artifact: Option<Vec<u8>> = redis::cmd("GET")
+        .arg("turborepo-test:team/prefixed")
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+
+    assert_eq!(artifact.as_deref(), Some(&b"artifact"[..]));
+}
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_REDIS_URL"]
+async fn rejects_announced_large_artifacts() {
+    let storage = RedisStorageAdapter::builder()
+        .with_url(url())
+        .with_max_artifact_size(4)
+        .build()
+        .await
+        .unwrap();
+
+    let err = storage
+        .upload_(PathBuf::from("team/large"), Body::from(vec![0; 5]))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, StorageAdapterError::QuotaExceeded(_)));
+}
+
+#[tokio::test]
+#[ignore = "requires TURBOREPO_TEST_REDIS_URL"]
+async fn metadata_expires_with_the_artifact() {
+    let storage = RedisStorageAdapter::builder()
+        .with_url(url())
+        .with_ttl(Duration::from_secs(1))
+        .build()
+        .await
+        .unwrap();
+    let path = PathBuf::from("team/expiring");
+
+    storage
+        .upload(path.clone(), Bytes::from("artifact"))
+        .await
+        .unwrap();
+    // The sidecar, written later, also expires later.
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    storage
+        .put_metadata(
+            path.clone(),
+            &ArtifactMetadata {
+                size: 8,
+                duration: None,
+                tag: None,
+                content_type: None,
+                uploaded_at: SystemTime::now(),
+            },
+        )
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    assert!(storage.stat(path.clone()).await.unwrap().is_none());
+    assert!(storage.get_metadata(path).await.unwrap().is_none());
+}
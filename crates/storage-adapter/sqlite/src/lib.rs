@@ -0,0 +1,531 @@
+use std::{
+    io::Seek,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use hyper::Body;
+use rusqlite::{
+    blob::ZeroBlob, params, Connection, DatabaseName, ErrorCode, OpenFlags, OptionalExtension,
+};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{mpsc, oneshot},
+};
+use turborepo_storage_adapter::{
+    ArtifactMetadata, ArtifactStream, ObjectEntries, ObjectEntry, ObjectInfo, StorageAdapter,
+    StorageAdapterError,
+};
+
+/// Artifacts are read by chunks of this size.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Number of artifacts [`SqliteStorageAdapter::list`] reads per query.
+const LIST_PAGE_SIZE: usize = 1000;
+/// How long a connection waits for another one to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stores an artifact, dropping the metadata of the one it replaces, and
+/// returns the row its blob can be opened at.
+const UPSERT: &str = "INSERT INTO artifacts (path, size, last_modified, metadata, data) \
+                      VALUES (?1, ?2, ?3, NULL, ?4) \
+                      ON CONFLICT (path) DO UPDATE SET \
+                          size = excluded.size, \
+                          last_modified = excluded.last_modified, \
+                          metadata = NULL, \
+                          data = excluded.data \
+                      RETURNING rowid";
+
+/// Keeps the artifacts and their metadata in a single SQLite database, in WAL
+/// mode, which suits single-node deployments: backups are one file.
+///
+/// Artifacts land in the `artifacts` table, one row per artifact, so the
+/// cache can be listed, measured and pruned with plain SQL, e.g.
+///
+/// ```sql
+/// SELECT substr(path, 1, instr(path, '/') - 1) AS team_id,
+///        COUNT(*) AS artifacts,
+///        SUM(size) AS size
+/// FROM artifacts
+/// GROUP BY team_id;
+///
+/// DELETE FROM artifacts
+/// WHERE last_modified < (unixepoch() - 30 * 24 * 3600) * 1000;
+/// ```
+///
+/// Uploads are spooled to a temporary file next to the database, then copied
+/// into it with incremental blob I/O, and downloads are streamed the same
+/// way.
+pub struct SqliteStorageAdapter {
+    path: PathBuf,
+    /// Connection uploads go through; SQLite allows one writer at a time.
+    writer: Arc<Mutex<Connection>>,
+    /// Connection of the short reads, so they don't wait for uploads.
+    reader: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorageAdapter {
+    pub fn builder() -> SqliteStorageAdapterBuilder {
+        SqliteStorageAdapterBuilder { path: None }
+    }
+}
+
+#[async_trait]
+impl StorageAdapter for SqliteStorageAdapter {
+    async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError> {
+        let key = key(&path);
+
+        run(&self.reader, move |connection| {
+            connection
+                .query_row("SELECT data FROM artifacts WHERE path = ?1", [key], |row| {
+                    row.get::<_, Vec<u8>>(0)
+                })
+                .optional()
+        })
+        .await?
+        .map(Bytes::from)
+        .ok_or_else(|| not_found(&path))
+    }
+
+    async fn get_(&self, path: PathBuf) -> Result<ArtifactStream, StorageAdapterError> {
+        let key = key(&path);
+        let database = self.path.clone();
+        let (size_sender, size_receiver) = oneshot::channel();
+        let (chunk_sender, chunk_receiver) = mpsc::channel(4);
+
+        // The blob is read from a connection of its own, so that slow
+        // downloads don't hold up other requests.
+        tokio::task::spawn_blocking(move || {
+            let mut size_sender = Some(size_sender);
+
+            if let Err(err) = read_blob(&database, &key, &mut size_sender, &chunk_sender) {
+                match size_sender {
+                    Some(size_sender) => {
+                        let _ = size_sender.send(Err(err));
+                    }
+                    None => {
+                        let _ = chunk_sender.blocking_send(Err(err));
+                    }
+                }
+            }
+        });
+
+        let size = size_receiver
+            .await
+            .map_err(|err| StorageAdapterError::Other(err.into()))?
+            .map_err(classify)?
+            .ok_or_else(|| not_found(&path))?;
+
+        let chunks = futures::stream::unfold(chunk_receiver, |mut chunk_receiver| async move {
+            chunk_receiver
+                .recv()
+                .await
+                .map(|chunk| (chunk, chunk_receiver))
+        });
+
+        Ok(ArtifactStream {
+            body: Body::wrap_stream(chunks),
+            size,
+        })
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError> {
+        let key = key(&path);
+
+        run(&self.reader, move |connection| {
+            connection.query_row(
+                "SELECT EXISTS (SELECT 1 FROM artifacts WHERE path = ?1)",
+                [key],
+                |row| row.get(0),
+            )
+        })
+        .await
+    }
+
+    async fn stat(&self, path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        let key = key(&path);
+
+        run(&self.reader, move |connection| {
+            connection
+                .query_row(
+                    "SELECT size, last_modified FROM artifacts WHERE path = ?1",
+                    [key],
+                    |row| {
+                        Ok(ObjectInfo {
+                            size: row.get(0)?,
+                            last_modified: Some(UNIX_EPOCH + Duration::from_millis(row.get(1)?)),
+                        })
+                    },
+                )
+                .optional()
+        })
+        .await
+    }
+
+    /// Lists the artifacts in path order, a page at a time, each page
+    /// starting after the last artifact of the previous one.
+    fn list(&self, prefix: PathBuf) -> ObjectEntries<'_> {
+        let prefix = key(&prefix);
+
+        futures::stream::try_unfold(Some(None), move |after: Option<Option<String>>| {
+            let prefix = prefix.clone();
+
+            async move {
+                let Some(after) = after else {
+                    return Ok(None);
+                };
+
+                let page = run(&self.reader, move |connection| {
+                    connection
+                        .prepare_cached(
+                            "SELECT path, size, last_modified FROM artifacts \
+                             WHERE path >= ?1 AND substr(path, 1, length(?1)) = ?1 \
+                                 AND (?2 IS NULL OR path > ?2) \
+                             ORDER BY path \
+                             LIMIT ?3",
+                        )?
+                        .query_map(params![prefix, after, LIST_PAGE_SIZE], |row| {
+                            Ok(ObjectEntry {
+                                path: row.get::<_, String>(0)?.into(),
+                                info: ObjectInfo {
+                                    size: row.get(1)?,
+                                    last_modified: Some(
+                                        UNIX_EPOCH + Duration::from_millis(row.get(2)?),
+                                    ),
+                                },
+                            })
+                        })?
+                        .collect::<rusqlite::Result<Vec<_>>>()
+                })
+                .await?;
+
+                let after = match page.len() {
+                    LIST_PAGE_SIZE => page
+                        .last()
+                        .map(|entry| Some(entry.path.to_string_lossy().into_owned())),
+                    _ => None,
+                };
+
+                Ok::<_, StorageAdapterError>(Some((
+                    futures::stream::iter(page.into_iter().map(Ok)),
+                    after,
+                )))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Removes the row of the artifact, its metadata included.
+    async fn delete(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        let key = key(&path);
+
+        run(&self.writer, move |connection| {
+            connection.execute("DELETE FROM artifacts WHERE path = ?1", [key])
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError> {
+        let key = key(&path);
+
+        run(&self.writer, move |connection| {
+            connection.query_row(
+                UPSERT,
+                params![key, artifact.len() as i64, now(), &artifact[..]],
+                |_| Ok(()),
+            )
+        })
+        .await
+    }
+
+    /// The body is spooled to a temporary file next to the database first,
+    /// so that a slow client doesn't hold up the other writes.
+    async fn upload_<'a>(
+        &self,
+        path: PathBuf,
+        mut artifact: Body,
+    ) -> Result<(), StorageAdapterError> {
+        let mut spool = fs::File::from_std(tempfile::tempfile_in(spool_dir(&self.path))?);
+
+        let mut size = 0;
+        while let Some(chunk) = artifact.next().await {
+            let chunk = chunk.map_err(|err| StorageAdapterError::Io(std::io::Error::other(err)))?;
+            spool.write_all(&chunk).await?;
+            size += chunk.len();
+        }
+        spool.flush().await?;
+
+        let size = i32::try_from(size).map_err(|_| {
+            StorageAdapterError::QuotaExceeded(
+                format!("{} bytes don't fit in a SQLite blob", size).into(),
+            )
+        })?;
+
+        let key = key(&path);
+        let mut spool = spool.into_std().await;
+        let writer = self.writer.clone();
+
+        tokio::task::spawn_blocking(move || {
+            spool.rewind()?;
+
+            let mut connection = writer.lock().unwrap();
+            let transaction = connection.transaction().map_err(classify)?;
+
+            let rowid: i64 = transaction
+                .query_row(UPSERT, params![key, size, now(), ZeroBlob(size)], |row| {
+                    row.get(0)
+                })
+                .map_err(classify)?;
+            let mut blob = transaction
+                .blob_open(DatabaseName::Main, "artifacts", "data", rowid, false)
+                .map_err(classify)?;
+            std::io::copy(&mut spool, &mut blob)?;
+            blob.close().map_err(classify)?;
+
+            transaction.commit().map_err(classify)
+        })
+        .await
+        .map_err(|err| StorageAdapterError::Other(err.into()))?
+    }
+
+    async fn get_metadata(
+        &self,
+        path: PathBuf,
+    ) -> Result<Option<ArtifactMetadata>, StorageAdapterError> {
+        let key = key(&path);
+
+        let metadata = run(&self.reader, move |connection| {
+            connection
+                .query_row(
+                    "SELECT metadata FROM artifacts WHERE path = ?1",
+                    [key],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()
+        })
+        .await?;
+
+        metadata
+            .flatten()
+            .map(|metadata| serde_json::from_str(&metadata).map_err(std::io::Error::from))
+            .transpose()
+            .map_err(StorageAdapterError::from)
+    }
+
+    /// Metadata is stored in the row of its artifact, which must exist.
+    async fn put_metadata(
+        &self,
+        path: PathBuf,
+        metadata: &ArtifactMetadata,
+    ) -> Result<(), StorageAdapterError> {
+        let key = key(&path);
+        let metadata = serde_json::to_string(metadata).map_err(std::io::Error::from)?;
+
+        let updated = run(&self.writer, move |connection| {
+            connection.execute(
+                "UPDATE artifacts SET metadata = ?2 WHERE path = ?1",
+                params![key, metadata],
+            )
+        })
+        .await?;
+
+        match updated {
+            0 => Err(not_found(&path)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        let key = key(&path);
+
+        run(&self.writer, move |connection| {
+            connection.execute(
+                "UPDATE artifacts SET metadata = NULL WHERE path = ?1",
+                params![key],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Runs `f` on `connection`, off the async runtime.
+async fn run<T, F>(connection: &Arc<Mutex<Connection>>, f: F) -> Result<T, StorageAdapterError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+{
+    let connection = connection.clone();
+
+    tokio::task::spawn_blocking(move || f(&mut connection.lock().unwrap()))
+        .await
+        .map_err(|err| StorageAdapterError::Other(err.into()))?
+        .map_err(classify)
+}
+
+/// Streams the blob of the artifact stored at `key` to `chunk_sender`, after
+/// sending its size, or `None` if there is none, to `size_sender`.
+fn read_blob(
+    database: &Path,
+    key: &str,
+    size_sender: &mut Option<oneshot::Sender<rusqlite::Result<Option<u64>>>>,
+    chunk_sender: &mpsc::Sender<rusqlite::Result<Bytes>>,
+) -> rusqlite::Result<()> {
+    let connection = open(
+        database,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    // Reads a snapshot of the database, so the artifact can't be replaced
+    // while it's streamed.
+    let transaction = connection.unchecked_transaction()?;
+
+    let rowid = transaction
+        .query_row(
+            "SELECT rowid FROM artifacts WHERE path = ?1",
+            [key],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+
+    let Some(rowid) = rowid else {
+        if let Some(size_sender) = size_sender.take() {
+            let _ = size_sender.send(Ok(None));
+        }
+
+        return Ok(());
+    };
+
+    let blob = transaction.blob_open(DatabaseName::Main, "artifacts", "data", rowid, true)?;
+
+    if let Some(size_sender) = size_sender.take() {
+        let _ = size_sender.send(Ok(Some(blob.len() as u64)));
+    }
+
+    let mut read = 0;
+    while read < blob.len() {
+        let mut chunk = vec![0; CHUNK_SIZE.min(blob.len() - read)];
+        blob.read_at_exact(&mut chunk, read)?;
+        read += chunk.len();
+
+        // The response was dropped.
+        if chunk_sender.blocking_send(Ok(chunk.into())).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Directory uploads are spooled to, the one of the database so that they
+/// don't fill up a smaller temporary file system.
+fn spool_dir(database: &Path) -> &Path {
+    match database.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn open(path: &Path, flags: OpenFlags) -> rusqlite::Result<Connection> {
+    let connection = Connection::open_with_flags(path, flags)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+
+    Ok(connection)
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn not_found(path: &Path) -> StorageAdapterError {
+    StorageAdapterError::NotFound(format!("{:?} is not stored", path).into())
+}
+
+fn classify(err: rusqlite::Error) -> StorageAdapterError {
+    let code = match &err {
+        rusqlite::Error::QueryReturnedNoRows => {
+            return StorageAdapterError::NotFound(err.into());
+        }
+        rusqlite::Error::SqliteFailure(failure, _) => failure.code,
+        _ => return StorageAdapterError::Other(err.into()),
+    };
+
+    match code {
+        ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked | ErrorCode::CannotOpen => {
+            StorageAdapterError::Unavailable(err.into())
+        }
+        ErrorCode::PermissionDenied | ErrorCode::ReadOnly => {
+            StorageAdapterError::PermissionDenied(err.into())
+        }
+        ErrorCode::DiskFull | ErrorCode::TooBig => StorageAdapterError::QuotaExceeded(err.into()),
+        _ => StorageAdapterError::Other(err.into()),
+    }
+}
+
+pub struct SqliteStorageAdapterBuilder {
+    path: Option<PathBuf>,
+}
+
+impl SqliteStorageAdapterBuilder {
+    /// Opens the database, creating it if needed.
+    pub async fn build(&self) -> Result<SqliteStorageAdapter, StorageAdapterError> {
+        let path = self.path.clone().unwrap();
+        let database = path.clone();
+
+        let (writer, reader) = tokio::task::spawn_blocking(move || {
+            let writer = open(&database, OpenFlags::default())?;
+
+            // Sizes and metadata come before the blob, so that reading them
+            // doesn't go through the overflow pages of the artifact.
+            writer.execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 CREATE TABLE IF NOT EXISTS artifacts (
+                     path TEXT PRIMARY KEY,
+                     size INTEGER NOT NULL,
+                     last_modified INTEGER NOT NULL,
+                     metadata TEXT,
+                     data BLOB NOT NULL
+                 );
+                 CREATE INDEX IF NOT EXISTS artifacts_last_modified
+                     ON artifacts (last_modified);",
+            )?;
+
+            let reader = open(
+                &database,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?;
+
+            Ok::<_, rusqlite::Error>((writer, reader))
+        })
+        .await
+        .map_err(|err| StorageAdapterError::Other(err.into()))?
+        .map_err(classify)?;
+
+        Ok(SqliteStorageAdapter {
+            path,
+            writer: Arc::new(Mutex::new(writer)),
+            reader: Arc::new(Mutex::new(reader)),
+        })
+    }
+
+    /// Path of the database file.
+    pub fn with_path(&mut self, path: PathBuf) -> &mut Self {
+        self.path.replace(path);
+
+        self
+    }
+}
@@ -0,0 +1,13 @@
+use turborepo_sqlite_storage_adapter::SqliteStorageAdapter;
+
+#[tokio::test]
+async fn conformance() {
+    let directory = tempfile::tempdir().unwrap();
+    let storage = SqliteStorageAdapter::builder()
+        .with_path(directory.path().join("turborepo.db"))
+        .build()
+        .await
+        .unwrap();
+
+    turborepo_storage_adapter_tests::run(&storage).await;
+}
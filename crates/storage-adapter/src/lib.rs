@@ -1,22 +1,222 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt};
 use hyper::Body;
+use serde::{Deserialize, Serialize};
+
+type Source = Box<dyn std::error::Error + Send + Sync>;
 
 #[derive(Debug, thiserror::Error)]
 pub enum StorageAdapterError {
     #[error("unknown error")]
     Unknown,
+    #[error("not found: {0}")]
+    NotFound(#[source] Source),
+    #[error("permission denied: {0}")]
+    PermissionDenied(#[source] Source),
+    /// The backend can't be reached or is overloaded; the operation may
+    /// succeed if retried.
+    #[error("storage unavailable: {0}")]
+    Unavailable(#[source] Source),
+    #[error("invalid key: {0}")]
+    InvalidKey(#[source] Source),
+    #[error("quota exceeded: {0}")]
+    QuotaExceeded(#[source] Source),
+    #[error("I/O error: {0}")]
+    Io(#[source] std::io::Error),
+    #[error("storage error: {0}")]
+    Other(#[source] Source),
+    /// The adapter doesn't implement the operation.
+    #[error("unsupported operation: {0}")]
+    Unsupported(&'static str),
+}
+
+impl StorageAdapterError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, StorageAdapterError::NotFound(_))
+    }
+
+    /// Whether the failure is transient, so the operation is worth retrying.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, StorageAdapterError::Unavailable(_))
+    }
+}
+
+impl From<std::io::Error> for StorageAdapterError {
+    fn from(value: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+
+        match value.kind() {
+            ErrorKind::NotFound => StorageAdapterError::NotFound(value.into()),
+            ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => {
+                StorageAdapterError::PermissionDenied(value.into())
+            }
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded | ErrorKind::FileTooLarge => {
+                StorageAdapterError::QuotaExceeded(value.into())
+            }
+            ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::ResourceBusy
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected => StorageAdapterError::Unavailable(value.into()),
+            ErrorKind::InvalidInput | ErrorKind::InvalidFilename | ErrorKind::IsADirectory => {
+                StorageAdapterError::InvalidKey(value.into())
+            }
+            _ => StorageAdapterError::Io(value),
+        }
+    }
+}
+
+/// Details of an artifact, persisted next to its blob.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactMetadata {
+    /// Size of the artifact, in bytes.
+    pub size: u64,
+    /// Time, in milliseconds, the task producing the artifact took to run;
+    /// the `x-artifact-duration` header.
+    pub duration: Option<u64>,
+    /// Signature of the artifact; the `x-artifact-tag` header.
+    pub tag: Option<String>,
+    pub content_type: Option<String>,
+    pub uploaded_at: SystemTime,
+}
+
+/// What the storage knows about a stored object, without reading it.
+#[derive(Clone, Debug)]
+pub struct ObjectInfo {
+    /// Size of the object, in bytes.
+    pub size: u64,
+    pub last_modified: Option<SystemTime>,
+}
+
+/// An artifact found by [`StorageAdapter::list`].
+#[derive(Clone, Debug)]
+pub struct ObjectEntry {
+    /// Path of the artifact, as passed to the other methods of the storage.
+    pub path: PathBuf,
+    pub info: ObjectInfo,
+}
+
+/// The artifacts yielded by [`StorageAdapter::list`].
+pub type ObjectEntries<'a> = BoxStream<'a, Result<ObjectEntry, StorageAdapterError>>;
+
+/// An artifact being read from the storage.
+pub struct ArtifactStream {
+    pub body: Body,
+    /// Size of the artifact, in bytes.
+    pub size: u64,
 }
 
 #[async_trait]
 pub trait StorageAdapter: Send + Sync {
     async fn get(&self, path: PathBuf) -> Result<Bytes, StorageAdapterError>;
 
+    /// Reads the artifact stored at `path` as a stream, without loading it
+    /// in memory.
+    ///
+    /// By default, it falls back to [`get`](Self::get).
+    async fn get_(&self, path: PathBuf) -> Result<ArtifactStream, StorageAdapterError> {
+        let artifact = self.get(path).await?;
+
+        Ok(ArtifactStream {
+            size: artifact.len() as u64,
+            body: artifact.into(),
+        })
+    }
+
     async fn exists(&self, path: PathBuf) -> Result<bool, StorageAdapterError>;
 
+    /// Returns the size and modification time of the object stored at
+    /// `path`, or `None` when there is none.
+    ///
+    /// By default, it fails with [`StorageAdapterError::Unsupported`].
+    async fn stat(&self, _path: PathBuf) -> Result<Option<ObjectInfo>, StorageAdapterError> {
+        Err(StorageAdapterError::Unsupported("stat"))
+    }
+
+    /// Lists the artifacts whose path starts with `prefix`, compared as
+    /// strings. Their metadata isn't listed. Artifacts are fetched a page at a
+    /// time as the stream is polled, in no particular order.
+    ///
+    /// By default, the stream fails with [`StorageAdapterError::Unsupported`].
+    fn list(&self, _prefix: PathBuf) -> ObjectEntries<'_> {
+        futures::stream::once(async { Err(StorageAdapterError::Unsupported("list")) }).boxed()
+    }
+
+    /// Removes the artifact stored at `path`, along with its metadata.
+    /// Removing an artifact that doesn't exist succeeds.
+    ///
+    /// By default, it fails with [`StorageAdapterError::Unsupported`].
+    async fn delete(&self, _path: PathBuf) -> Result<(), StorageAdapterError> {
+        Err(StorageAdapterError::Unsupported("delete"))
+    }
+
     async fn upload<'a>(&self, path: PathBuf, artifact: Bytes) -> Result<(), StorageAdapterError>;
 
     async fn upload_<'a>(&self, path: PathBuf, artifact: Body) -> Result<(), StorageAdapterError>;
+
+    /// Returns the metadata of the artifact stored at `path`, if any.
+    ///
+    /// By default, metadata is read as JSON from a sidecar object next to the
+    /// artifact (see [`metadata_path`]).
+    async fn get_metadata(
+        &self,
+        path: PathBuf,
+    ) -> Result<Option<ArtifactMetadata>, StorageAdapterError> {
+        match self.get(metadata_path(&path)).await {
+            Ok(metadata) => Ok(Some(
+                serde_json::from_slice(&metadata).map_err(std::io::Error::from)?,
+            )),
+            Err(err) if err.is_not_found() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Stores the metadata of the artifact at `path`, replacing any previous
+    /// one.
+    ///
+    /// By default, metadata is written as JSON to a sidecar object next to
+    /// the artifact (see [`metadata_path`]).
+    async fn put_metadata(
+        &self,
+        path: PathBuf,
+        metadata: &ArtifactMetadata,
+    ) -> Result<(), StorageAdapterError> {
+        let metadata = serde_json::to_vec(metadata).map_err(std::io::Error::from)?;
+
+        self.upload(metadata_path(&path), metadata.into()).await
+    }
+
+    /// Removes the metadata of the artifact stored at `path`, if any, leaving
+    /// the artifact in place.
+    ///
+    /// By default, the sidecar object next to the artifact (see
+    /// [`metadata_path`]) is removed with [`delete`](Self::delete).
+    async fn delete_metadata(&self, path: PathBuf) -> Result<(), StorageAdapterError> {
+        self.delete(metadata_path(&path)).await
+    }
+}
+
+/// Path of the sidecar object holding the metadata of the artifact at `path`.
+pub fn metadata_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".meta");
+
+    path.into()
+}
+
+/// Whether `path` is the sidecar holding the metadata of another object (see
+/// [`metadata_path`]), which adapters storing metadata as sidecars leave out of
+/// [`StorageAdapter::list`].
+pub fn is_metadata_path(path: &Path) -> bool {
+    path.as_os_str().to_string_lossy().ends_with(".meta")
 }
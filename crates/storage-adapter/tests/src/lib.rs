@@ -0,0 +1,365 @@
+//! Checks that a [`StorageAdapter`] behaves the way the server expects, so
+//! every backend is held to the same contract. Call [`run`] from a test of the
+//! adapter:
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn conformance() {
+//!     let storage = MemoryStorageAdapter::builder().build();
+//!
+//!     turborepo_storage_adapter_tests::run(&storage).await;
+//! }
+//! ```
+//!
+//! Each check uses paths of its own, under a team no other run uses, so the
+//! suite can run against a shared bucket or database. A failed check panics.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use hyper::Body;
+use turborepo_storage_adapter::{ArtifactMetadata, StorageAdapter};
+
+/// Size of the artifact streamed by [`large_streamed_body`], above the part
+/// and block sizes of the cloud adapters.
+const LARGE_SIZE: usize = 12 * 1024 * 1024;
+/// Size of the chunks bodies are streamed in.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Number of uploads [`concurrent_writers`] runs at once.
+const WRITERS: u8 = 8;
+
+/// Distinguishes the teams of the checks of a run.
+static TEAM_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Runs every check against `storage`.
+pub async fn run(storage: &dyn StorageAdapter) {
+    round_trip(storage).await;
+    exists_after_upload(storage).await;
+    overwrite(storage).await;
+    large_streamed_body(storage).await;
+    empty_body(storage).await;
+    not_found(storage).await;
+    concurrent_writers(storage).await;
+    odd_keys(storage).await;
+    metadata_round_trip(storage).await;
+    delete(storage).await;
+    list(storage).await;
+}
+
+/// An uploaded artifact is read back as is, whole or streamed.
+pub async fn round_trip(storage: &dyn StorageAdapter) {
+    let path = path("round-trip");
+    let artifact = Bytes::from_static(b"round trip");
+
+    storage
+        .upload(path.clone(), artifact.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(storage.get(path.clone()).await.unwrap(), artifact);
+
+    let stored = storage.get_(path.clone()).await.unwrap();
+    assert_eq!(stored.size, artifact.len() as u64);
+    assert_eq!(hyper::body::to_bytes(stored.body).await.unwrap(), artifact);
+}
+
+/// An artifact exists, with its size, once uploaded, and not before.
+pub async fn exists_after_upload(storage: &dyn StorageAdapter) {
+    let path = path("exists");
+
+    assert!(!storage.exists(path.clone()).await.unwrap());
+
+    storage
+        .upload(path.clone(), Bytes::from_static(b"exists"))
+        .await
+        .unwrap();
+
+    assert!(storage.exists(path.clone()).await.unwrap());
+    assert_eq!(storage.stat(path).await.unwrap().unwrap().size, 6);
+}
+
+/// Uploading an artifact again replaces it entirely, even with a shorter one.
+pub async fn overwrite(storage: &dyn StorageAdapter) {
+    let path = path("overwrite");
+
+    storage
+        .upload(path.clone(), Bytes::from_static(b"a longer first artifact"))
+        .await
+        .unwrap();
+    storage
+        .upload_(path.clone(), Body::from("second"))
+        .await
+        .unwrap();
+
+    assert_eq!(storage.get(path.clone()).await.unwrap(), "second");
+    assert_eq!(storage.stat(path).await.unwrap().unwrap().size, 6);
+}
+
+/// A large artifact of unknown size is uploaded and read back as a stream.
+pub async fn large_streamed_body(storage: &dyn StorageAdapter) {
+    let path = path("large");
+    // 251 being prime, chunks reordered or repeated change the content.
+    let artifact: Bytes = (0..LARGE_SIZE).map(|i| (i % 251) as u8).collect();
+
+    storage
+        .upload_(path.clone(), streamed(artifact.clone()))
+        .await
+        .unwrap();
+
+    let stored = storage.get_(path.clone()).await.unwrap();
+    assert_eq!(stored.size, LARGE_SIZE as u64);
+
+    let body = hyper::body::to_bytes(stored.body).await.unwrap();
+    assert_eq!(body.len(), LARGE_SIZE);
+    assert!(body == artifact, "the streamed artifact was altered");
+
+    assert_eq!(
+        storage.stat(path).await.unwrap().unwrap().size,
+        LARGE_SIZE as u64
+    );
+}
+
+/// Empty artifacts are stored, and exist.
+pub async fn empty_body(storage: &dyn StorageAdapter) {
+    let uploaded = path("empty");
+    let streamed = path("empty-streamed");
+
+    storage
+        .upload(uploaded.clone(), Bytes::new())
+        .await
+        .unwrap();
+    storage
+        .upload_(streamed.clone(), Body::empty())
+        .await
+        .unwrap();
+
+    for path in [uploaded, streamed] {
+        assert!(storage.exists(path.clone()).await.unwrap());
+        assert_eq!(storage.stat(path.clone()).await.unwrap().unwrap().size, 0);
+        assert!(storage.get(path.clone()).await.unwrap().is_empty());
+
+        let stored = storage.get_(path).await.unwrap();
+        assert_eq!(stored.size, 0);
+        assert!(hyper::body::to_bytes(stored.body).await.unwrap().is_empty());
+    }
+}
+
+/// Missing artifacts fail with [`NotFound`] where an artifact is expected.
+///
+/// [`NotFound`]: turborepo_storage_adapter::StorageAdapterError::NotFound
+pub async fn not_found(storage: &dyn StorageAdapter) {
+    let path = path("missing");
+
+    let err = storage.get(path.clone()).await.unwrap_err();
+    assert!(err.is_not_found(), "get failed with {:?}", err);
+
+    let err = storage.get_(path.clone()).await.err().unwrap();
+    assert!(err.is_not_found(), "get_ failed with {:?}", err);
+
+    assert!(!storage.exists(path.clone()).await.unwrap());
+    assert!(storage.stat(path).await.unwrap().is_none());
+}
+
+/// Concurrent uploads of the same artifact leave one of them, whole.
+pub async fn concurrent_writers(storage: &dyn StorageAdapter) {
+    let path = path("concurrent");
+
+    let uploads = (1..=WRITERS).map(|writer| {
+        // Several chunks, so that the uploads interleave.
+        let artifact = Bytes::from(vec![writer; 8 * CHUNK_SIZE]);
+
+        storage.upload_(path.clone(), streamed(artifact))
+    });
+    for uploaded in futures::future::join_all(uploads).await {
+        uploaded.unwrap();
+    }
+
+    let stored = storage.get(path).await.unwrap();
+    assert_eq!(stored.len(), 8 * CHUNK_SIZE);
+    assert!(
+        (1..=WRITERS).contains(&stored[0]) && stored.iter().all(|&byte| byte == stored[0]),
+        "the uploads were mixed up"
+    );
+}
+
+/// Keys with spaces, punctuation or non-ASCII characters are stored as
+/// distinct artifacts.
+pub async fn odd_keys(storage: &dyn StorageAdapter) {
+    let team = team();
+    let hashes = [
+        "with space",
+        "plus+sign",
+        "percent%20encoded",
+        "query?and#fragment",
+        "equals=and&ampersand",
+        "quotes'\"",
+        "brackets[]{}()",
+        "ünïcödé",
+        "日本語",
+        "emoji🦀",
+        "MixedCase",
+        ".leading-dot",
+        "trailing-dot.",
+    ];
+
+    for hash in hashes {
+        storage
+            .upload(format!("{}/{}", team, hash).into(), Bytes::from(hash))
+            .await
+            .unwrap_or_else(|err| panic!("can't upload {:?}: {:?}", hash, err));
+    }
+
+    for hash in hashes {
+        let path = PathBuf::from(format!("{}/{}", team, hash));
+
+        assert!(storage.exists(path.clone()).await.unwrap(), "{:?}", hash);
+        assert_eq!(storage.get(path).await.unwrap(), hash);
+    }
+}
+
+/// Metadata is read back as stored, and dropped when the artifact is
+/// uploaded again.
+pub async fn metadata_round_trip(storage: &dyn StorageAdapter) {
+    let path = path("metadata");
+
+    storage
+        .upload(path.clone(), Bytes::from_static(b"metadata"))
+        .await
+        .unwrap();
+    assert_eq!(storage.get_metadata(path.clone()).await.unwrap(), None);
+
+    storage
+        .put_metadata(path.clone(), &metadata("first"))
+        .await
+        .unwrap();
+    storage
+        .put_metadata(path.clone(), &metadata("second"))
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.get_metadata(path.clone()).await.unwrap(),
+        Some(metadata("second"))
+    );
+}
+
+/// A deleted artifact is gone, along with its metadata. Deleting a missing
+/// artifact succeeds.
+pub async fn delete(storage: &dyn StorageAdapter) {
+    let path = path("delete");
+
+    storage
+        .upload(path.clone(), Bytes::from_static(b"delete"))
+        .await
+        .unwrap();
+    storage
+        .put_metadata(path.clone(), &metadata("delete"))
+        .await
+        .unwrap();
+
+    storage.delete(path.clone()).await.unwrap();
+
+    assert!(!storage.exists(path.clone()).await.unwrap());
+    assert!(storage.get(path.clone()).await.unwrap_err().is_not_found());
+    assert_eq!(storage.get_metadata(path.clone()).await.unwrap(), None);
+
+    storage.delete(path).await.unwrap();
+}
+
+/// Listing yields the artifacts under a prefix, with their sizes, and not
+/// their metadata.
+pub async fn list(storage: &dyn StorageAdapter) {
+    let team = team();
+    let artifacts = [("a-1", "a"), ("a-2", "aa"), ("b-1", "bbb")];
+
+    for (hash, artifact) in artifacts {
+        let path = PathBuf::from(format!("{}/{}", team, hash));
+
+        storage
+            .upload(path.clone(), Bytes::from(artifact))
+            .await
+            .unwrap();
+        storage.put_metadata(path, &metadata(hash)).await.unwrap();
+    }
+
+    let listed = |prefix: String| async move {
+        storage
+            .list(prefix.into())
+            .map_ok(|entry| (entry.path, entry.info.size))
+            .try_collect::<BTreeMap<_, _>>()
+            .await
+            .unwrap()
+    };
+
+    let expected = |hashes: &[&str]| {
+        artifacts
+            .iter()
+            .filter(|(hash, _)| hashes.contains(hash))
+            .map(|(hash, artifact)| {
+                (
+                    PathBuf::from(format!("{}/{}", team, hash)),
+                    artifact.len() as u64,
+                )
+            })
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    assert_eq!(
+        listed(format!("{}/", team)).await,
+        expected(&["a-1", "a-2", "b-1"])
+    );
+    assert_eq!(
+        listed(format!("{}/a", team)).await,
+        expected(&["a-1", "a-2"])
+    );
+    assert_eq!(listed(format!("{}/c", team)).await, expected(&[]));
+}
+
+fn metadata(tag: &str) -> ArtifactMetadata {
+    ArtifactMetadata {
+        size: 8,
+        duration: Some(1200),
+        tag: Some(tag.into()),
+        content_type: Some("application/octet-stream".into()),
+        uploaded_at: UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123),
+    }
+}
+
+/// A team no other check or run uses.
+fn team() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    format!(
+        "conformance-{}-{}",
+        now,
+        TEAM_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// A path under a team no other check or run uses, for the checks an
+/// adapter has of its own.
+pub fn path(hash: &str) -> PathBuf {
+    format!("{}/{}", team(), hash).into()
+}
+
+/// Streams `artifact` in chunks, without a size hint.
+fn streamed(artifact: Bytes) -> Body {
+    let chunks = (0..artifact.len())
+        .step_by(CHUNK_SIZE)
+        .map(move |start| {
+            let end = artifact.len().min(start + CHUNK_SIZE);
+
+            Ok::<_, std::io::Error>(artifact.slice(start..end))
+        })
+        .collect::<Vec<_>>();
+
+    Body::wrap_stream(futures::stream::iter(chunks))
+}